@@ -0,0 +1,262 @@
+//! Sound effects and the fleet march. Every sound is synthesized at startup,
+//! so there are no audio files to ship. Without an audio device `Audio` is
+//! still constructed and every call is a no-op.
+
+use std::f32::consts::TAU;
+
+use sdl2::{
+    mixer::{
+        self,
+        Channel,
+        Chunk,
+    },
+    AudioSubsystem,
+    Sdl,
+};
+use serde::Deserialize;
+
+use crate::{
+    rng::Rng,
+    world::GameEvent,
+};
+
+/// Rate asked for; the device may open at another, see `Sounds::synthesize`.
+const FREQUENCY: i32 = 22_050;
+const CHUNK_SIZE: i32 = 512;
+const AMPLITUDE: f32 = 6000.0;
+
+const CHANNELS: i32 = 8;
+/// Reserved so effects never steal them.
+const MARCH_CHANNEL: Channel = Channel(0);
+const MOTHERSHIP_CHANNEL: Channel = Channel(1);
+
+/// The four descending bass notes of the fleet march, in Hz.
+const MARCH_NOTES: [f32; 4] = [98.0, 87.3, 77.8, 73.4];
+
+/// Levels in percent. Music is the march; everything else is an effect.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Volume {
+    pub master: u8,
+    pub sfx: u8,
+    pub music: u8,
+    pub muted: bool,
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Self { master: 80, sfx: 100, music: 100, muted: false }
+    }
+}
+
+impl Volume {
+    // Mixer volume (0..=128) for a channel playing at `part` percent.
+    fn level(&self, part: u8) -> i32 {
+        if self.muted {
+            return 0;
+        }
+        mixer::MAX_VOLUME * self.master.min(100) as i32 * part.min(100) as i32 / 10_000
+    }
+}
+
+struct Sounds {
+    shot: Chunk,
+    power_up: Chunk,
+    alien_death: Chunk,
+    mothership_death: Chunk,
+    player_hit: Chunk,
+    shield_hit: Chunk,
+    mothership: Chunk,
+    march: [Chunk; 4],
+}
+
+impl Sounds {
+    /// Chunks are handed to the mixer as raw samples, which it plays without
+    /// converting, so they are made in the format the device actually opened
+    /// with: mono samples at its rate, copied to each of its channels.
+    fn synthesize() -> Result<Self, String> {
+        let (rate, format, channels) = mixer::query_spec()?;
+        if format != mixer::AUDIO_S16SYS {
+            return Err(format!("audio device opened with unsupported sample format {:#x}", format));
+        }
+        let rate = rate as u32;
+        let mut rng = Rng::new(0x5EED_A0D1);
+        let chunk = |samples: Vec<i16>| {
+            let frames: Vec<i16> = samples.into_iter()
+                .flat_map(|s| std::iter::repeat_n(s, channels.max(1) as usize))
+                .collect();
+            Chunk::from_raw_buffer(frames.into_boxed_slice())
+        };
+        Ok(Self {
+            shot: chunk(sweep(rate, 1400.0, 300.0, 120))?,
+            power_up: chunk(sweep(rate, 500.0, 1800.0, 180))?,
+            alien_death: chunk(noise(&mut rng, rate, 220, 2, 12))?,
+            mothership_death: chunk(noise(&mut rng, rate, 600, 4, 30))?,
+            player_hit: chunk(noise(&mut rng, rate, 700, 8, 40))?,
+            shield_hit: chunk(noise(&mut rng, rate, 60, 3, 6))?,
+            mothership: chunk(warble(rate, 700.0, 150.0, 8.0, 500))?,
+            march: [
+                chunk(sweep(rate, MARCH_NOTES[0], MARCH_NOTES[0], 100))?,
+                chunk(sweep(rate, MARCH_NOTES[1], MARCH_NOTES[1], 100))?,
+                chunk(sweep(rate, MARCH_NOTES[2], MARCH_NOTES[2], 100))?,
+                chunk(sweep(rate, MARCH_NOTES[3], MARCH_NOTES[3], 100))?,
+            ],
+        })
+    }
+}
+
+// Closes the mixer when dropped. Declared after `Sounds` in `Output` so the
+// chunks are freed first.
+struct Device;
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        mixer::close_audio();
+    }
+}
+
+struct Output {
+    sounds: Sounds,
+    _device: Device,
+    _subsystem: AudioSubsystem,
+}
+
+pub struct Audio {
+    output: Option<Output>,
+    volume: Volume,
+    march_note: usize,
+}
+
+impl Audio {
+    /// Opens the default device. Any failure, including there being no
+    /// device at all, leaves the game silent instead of stopping it.
+    pub fn new(sdl: &Sdl, volume: Volume) -> Self {
+        let output = sdl.audio().and_then(|subsystem| {
+            mixer::open_audio(FREQUENCY, mixer::AUDIO_S16SYS, 1, CHUNK_SIZE)?;
+            let device = Device;
+            mixer::allocate_channels(CHANNELS);
+            mixer::reserve_channels(2);
+            Ok(Output { sounds: Sounds::synthesize()?, _device: device, _subsystem: subsystem })
+        });
+        let audio = Self { output: output.ok(), volume, march_note: 0 };
+        audio.apply_volume();
+        audio
+    }
+
+    pub fn volume(&self) -> Volume {
+        self.volume
+    }
+
+    pub fn set_volume(&mut self, volume: Volume) {
+        self.volume = volume;
+        self.apply_volume();
+    }
+
+    pub fn toggle_mute(&mut self) {
+        self.volume.muted = !self.volume.muted;
+        self.apply_volume();
+    }
+
+    fn apply_volume(&self) {
+        if self.output.is_none() {
+            return;
+        }
+        Channel::all().set_volume(self.volume.level(self.volume.sfx));
+        MARCH_CHANNEL.set_volume(self.volume.level(self.volume.music));
+    }
+
+    /// Plays whatever the step's events call for.
+    pub fn play_events(&mut self, events: &[GameEvent]) {
+        let Some(Output { sounds, .. }) = &self.output else { return };
+        for event in events {
+            let chunk = match event {
+                GameEvent::FleetStepped => {
+                    let note = &sounds.march[self.march_note];
+                    self.march_note = (self.march_note + 1) % sounds.march.len();
+                    let _ = MARCH_CHANNEL.play(note, 0);
+                    continue;
+                }
+                GameEvent::PlayerFired => &sounds.shot,
+                GameEvent::PowerUpCollected(_) => &sounds.power_up,
+                GameEvent::AlienKilled(..) => &sounds.alien_death,
+                GameEvent::MothershipKilled(..) => &sounds.mothership_death,
+                GameEvent::ShieldHit(_) | GameEvent::AlienHit(_) | GameEvent::ShotsCollided(_) => &sounds.shield_hit,
+                GameEvent::PlayerHit(_) => &sounds.player_hit,
+                GameEvent::WaveCleared | GameEvent::GameOver(_) => continue,
+            };
+            let _ = Channel::all().play(chunk, 0);
+        }
+    }
+
+    /// Keeps the mothership loop running exactly while it is on screen.
+    pub fn mothership(&self, flying: bool) {
+        let Some(Output { sounds, .. }) = &self.output else { return };
+        if flying && !MOTHERSHIP_CHANNEL.is_playing() {
+            let _ = MOTHERSHIP_CHANNEL.play(&sounds.mothership, -1);
+        } else if !flying && MOTHERSHIP_CHANNEL.is_playing() {
+            MOTHERSHIP_CHANNEL.halt();
+        }
+    }
+
+    /// Silences everything, e.g. when leaving a game. The march restarts
+    /// from its first note.
+    pub fn stop(&mut self) {
+        if self.output.is_some() {
+            Channel::all().halt();
+        }
+        self.march_note = 0;
+    }
+}
+
+// Every generator below makes mono samples at `rate` Hz.
+
+fn samples(rate: u32, ms: u32) -> usize {
+    rate as usize * ms as usize / 1000
+}
+
+/// Square wave gliding from `from` to `to` Hz, fading out linearly.
+fn sweep(rate: u32, from: f32, to: f32, ms: u32) -> Vec<i16> {
+    let n = samples(rate, ms);
+    let mut phase = 0.0f32;
+    (0..n)
+        .map(|i| {
+            let t = i as f32 / n as f32;
+            phase = (phase + (from + (to - from) * t) / rate as f32).fract();
+            let v = if phase < 0.5 { 1.0 } else { -1.0 };
+            (v * (1.0 - t) * AMPLITUDE) as i16
+        })
+        .collect()
+}
+
+/// White noise fading out. Each random value is held for a number of samples
+/// growing from `hold_from` to `hold_to`, which makes the pitch fall. Holds
+/// are counted at `FREQUENCY` and scaled to `rate`, so the pitch is the same
+/// at any rate.
+fn noise(rng: &mut Rng, rate: u32, ms: u32, hold_from: usize, hold_to: usize) -> Vec<i16> {
+    let n = samples(rate, ms);
+    let scale = rate as f32 / FREQUENCY as f32;
+    let mut out = Vec::with_capacity(n);
+    while out.len() < n {
+        let t = out.len() as f32 / n as f32;
+        let hold = ((hold_from as f32 + (hold_to - hold_from) as f32 * t) * scale).round() as usize;
+        let v = (rng.range(0, 2000) as f32 / 1000.0 - 1.0) * (1.0 - t) * AMPLITUDE;
+        out.extend(std::iter::repeat_n(v as i16, hold.max(1)));
+    }
+    out.truncate(n);
+    out
+}
+
+/// Square wave around `center` Hz, wobbling by `depth` Hz `wobble` times a
+/// second. Meant to loop, so it does not fade.
+fn warble(rate: u32, center: f32, depth: f32, wobble: f32, ms: u32) -> Vec<i16> {
+    let n = samples(rate, ms);
+    let mut phase = 0.0f32;
+    (0..n)
+        .map(|i| {
+            let t = i as f32 / rate as f32;
+            let freq = center + depth * (TAU * wobble * t).sin();
+            phase = (phase + freq / rate as f32).fract();
+            (if phase < 0.5 { AMPLITUDE } else { -AMPLITUDE }) as i16
+        })
+        .collect()
+}
@@ -0,0 +1,83 @@
+use std::{
+    collections::HashMap,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use sdl2::{
+    pixels::Color,
+    render::Canvas,
+    video::Window,
+};
+
+use crate::{
+    powerups::DropTable,
+    render::{
+        self,
+        SpriteTextures,
+    },
+    sprites::SpriteBank,
+    waves::{
+        Difficulty,
+        MothershipDef,
+        Shots,
+        WaveDef,
+        Waves,
+    },
+    world::{
+        Loadout,
+        World,
+    },
+};
+
+const FRAMES: u32 = 600;
+
+/// `--bench-render`: draws a full 60-alien wave with the old per-cell
+/// renderer and with cached textures and prints the average cost of a frame
+/// for each.
+pub fn run(canvas: &mut Canvas<Window>, textures: &mut SpriteTextures, sprites: SpriteBank) -> Result<(), String> {
+    let full_wave = WaveDef {
+        rows: ["alien_1", "alien_2", "alien_2", "alien_2", "alien_2"].map(String::from).to_vec(),
+        columns: 12,
+        start_height: 120,
+        step_interval_ms: 600,
+        fire_interval_ms: 900,
+        drop: 10,
+        drops: None,
+    };
+    let waves = Waves {
+        waves: vec![full_wave],
+        difficulty: Difficulty::default(),
+        drops: DropTable::default(),
+        kinds: HashMap::new(),
+        shots: Shots::default(),
+        mothership: MothershipDef::default(),
+    };
+    let world = World::new(sprites, waves, 0, Loadout::default()).map_err(|e| e.to_string())?;
+
+    let per_cell = time_frames(canvas, |canvas| render::draw_world_cells(canvas, &world));
+    let textured = time_frames(canvas, |canvas| render::draw_world(canvas, textures, &world));
+
+    println!("{} aliens, {} frames each", world.aliens.len(), FRAMES);
+    println!("per-cell fill_rect: {:>8.3} ms/frame", ms(per_cell));
+    println!("cached textures:    {:>8.3} ms/frame", ms(textured));
+    println!("speedup:            {:>8.2}x", per_cell.as_secs_f64() / textured.as_secs_f64());
+    Ok(())
+}
+
+fn time_frames(canvas: &mut Canvas<Window>, mut draw: impl FnMut(&mut Canvas<Window>)) -> Duration {
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.clear();
+        draw(canvas);
+        canvas.present();
+    }
+    start.elapsed() / FRAMES
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
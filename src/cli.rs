@@ -0,0 +1,104 @@
+use std::path::PathBuf;
+
+use crate::config;
+
+pub const USAGE: &str = "\
+usage: space-invade-rs [options]
+
+  --seed <n>        fix the RNG seed
+  --lives <n>       starting lives, 1 to 9
+  --scale <n>       window size multiplier, 1 to 4
+  --fullscreen      start in fullscreen
+  --config <file>   config file to use instead of config.toml
+  --data-dir <dir>  where high scores and replays are kept
+  --replay <file>   watch a recorded run
+  --bench-render    time the sprite renderers and exit
+  --help            show this message";
+
+/// Options given on the command line. Anything left `None` falls back to the
+/// config file, then to the defaults.
+#[derive(Debug, Default)]
+pub struct Args {
+    pub seed: Option<u64>,
+    pub lives: Option<u32>,
+    pub scale: Option<u32>,
+    pub fullscreen: Option<bool>,
+    pub config: Option<PathBuf>,
+    pub data_dir: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub bench_render: bool,
+    pub help: bool,
+}
+
+pub fn parse(args: &[String]) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut it = args.iter().skip(1);
+    while let Some(flag) = it.next() {
+        let mut value = || it.next().map(String::as_str).ok_or(format!("{} expects a value", flag));
+        match flag.as_str() {
+            "--seed" => {
+                let v = value()?;
+                parsed.seed = Some(v.parse().map_err(|_| format!("--seed expects a whole number, got \"{}\"", v))?);
+            }
+            "--lives" => parsed.lives = Some(number(flag, value()?, config::LIVES)?),
+            "--scale" => parsed.scale = Some(number(flag, value()?, config::SCALE)?),
+            "--fullscreen" => parsed.fullscreen = Some(true),
+            "--config" => parsed.config = Some(PathBuf::from(value()?)),
+            "--data-dir" => parsed.data_dir = Some(PathBuf::from(value()?)),
+            "--replay" => parsed.replay = Some(PathBuf::from(value()?)),
+            "--bench-render" => parsed.bench_render = true,
+            "--help" | "-h" => parsed.help = true,
+            other => return Err(format!("unknown option \"{}\"\n\n{}", other, USAGE)),
+        }
+    }
+    Ok(parsed)
+}
+
+fn number(flag: &str, value: &str, (lo, hi): (u32, u32)) -> Result<u32, String> {
+    value.parse::<u32>()
+        .ok()
+        .filter(|n| (lo..=hi).contains(n))
+        .ok_or(format!("{} expects a number from {} to {}, got \"{}\"", flag, lo, hi, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(flags: &[&str]) -> Result<Args, String> {
+        let argv: Vec<String> = std::iter::once("space-invade-rs").chain(flags.iter().copied()).map(String::from).collect();
+        parse(&argv)
+    }
+
+    #[test]
+    fn parses_flags() {
+        let a = args(&["--seed", "42", "--lives", "5", "--scale", "2", "--fullscreen", "--data-dir", "saves"]).unwrap();
+        assert_eq!(a.seed, Some(42));
+        assert_eq!(a.lives, Some(5));
+        assert_eq!(a.scale, Some(2));
+        assert_eq!(a.fullscreen, Some(true));
+        assert_eq!(a.data_dir, Some(PathBuf::from("saves")));
+        assert_eq!(a.config, None);
+    }
+
+    #[test]
+    fn no_flags_leave_everything_to_the_file() {
+        let a = args(&[]).unwrap();
+        assert_eq!((a.seed, a.lives, a.scale, a.fullscreen), (None, None, None, None));
+    }
+
+    #[test]
+    fn errors_name_the_flag() {
+        let cases = [
+            (&["--lives", "0"][..], "--lives expects a number from 1 to 9, got \"0\""),
+            (&["--lives", "10"][..], "--lives expects a number from 1 to 9, got \"10\""),
+            (&["--scale", "big"][..], "--scale expects a number from 1 to 4, got \"big\""),
+            (&["--seed", "-1"][..], "--seed expects a whole number, got \"-1\""),
+            (&["--seed"][..], "--seed expects a value"),
+        ];
+        for (flags, msg) in cases {
+            assert_eq!(args(flags).unwrap_err(), msg);
+        }
+        assert!(args(&["--lifes", "3"]).unwrap_err().starts_with("unknown option \"--lifes\""));
+    }
+}
@@ -0,0 +1,57 @@
+use std::time::{
+    Duration,
+    Instant,
+};
+
+/// Logical simulation rate. Every timer in the world is counted in these.
+pub const TICK_RATE: u64 = 60;
+
+/// Upper bound on ticks run in one frame so a long stall (window drag,
+/// debugger) doesn't turn into a burst of catch-up steps.
+const MAX_TICKS_PER_FRAME: u32 = 5;
+
+pub const fn ticks_from_ms(ms: u64) -> u64 {
+    ms * TICK_RATE / 1000
+}
+
+/// Turns wall-clock time into a whole number of fixed ticks, carrying the
+/// remainder over to the next frame.
+pub struct FixedClock {
+    tick: Duration,
+    accumulator: Duration,
+    last: Instant,
+}
+
+impl FixedClock {
+    pub fn new() -> Self {
+        Self {
+            tick: Duration::from_secs(1) / TICK_RATE as u32,
+            accumulator: Duration::ZERO,
+            last: Instant::now(),
+        }
+    }
+
+    /// Drops any pending time, e.g. when coming back from a pause.
+    pub fn reset(&mut self) {
+        self.accumulator = Duration::ZERO;
+        self.last = Instant::now();
+    }
+
+    /// Number of ticks that should be simulated now.
+    pub fn advance(&mut self) -> u32 {
+        let now = Instant::now();
+        self.accumulator += now - self.last;
+        self.last = now;
+
+        let mut ticks = 0;
+        while self.accumulator >= self.tick {
+            self.accumulator -= self.tick;
+            ticks += 1;
+        }
+        if ticks > MAX_TICKS_PER_FRAME {
+            ticks = MAX_TICKS_PER_FRAME;
+            self.accumulator = Duration::ZERO;
+        }
+        ticks
+    }
+}
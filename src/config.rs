@@ -0,0 +1,353 @@
+//! User settings from `config.toml`, overridden by command line flags. Only
+//! the settings that can be changed in-game (controls, volume, fire mode and
+//! effects) are ever written back, in place; everything else in the file,
+//! comments and layout included, is kept as it was.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs,
+    io,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use serde::Deserialize;
+use toml_edit::{
+    DocumentMut,
+    Item,
+    Value,
+};
+
+use crate::{
+    audio::Volume,
+    cli::Args,
+    clock::ticks_from_ms,
+    controls::Bindings,
+    effects::EffectsLevel,
+    persist,
+    world::{
+        FireMode,
+        Loadout,
+        Weapon,
+        DEFAULT_LIVES,
+        MAX_BULLET_SPEED,
+        MAX_LIVES,
+    },
+};
+
+pub const CONFIG_PATH: &str = "config.toml";
+pub const DEFAULT_FONT: &str = "assets/PressStart2P-Regular.ttf";
+
+/// Allowed values, inclusive, shared with the command line.
+pub const SCALE: (u32, u32) = (1, 4);
+pub const LIVES: (u32, u32) = (1, MAX_LIVES);
+pub const MAX_BULLETS: (u32, u32) = (1, 10);
+pub const COOLDOWN_MS: (u32, u32) = (0, 5000);
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Window size as a multiple of the playfield.
+    pub scale: u32,
+    pub fullscreen: bool,
+    pub lives: u32,
+    /// Fixed RNG seed; every game is random without one.
+    pub seed: Option<u64>,
+    pub font: PathBuf,
+    /// Overrides the per-user directory SDL picks.
+    pub data_dir: Option<PathBuf>,
+    pub volume: Volume,
+    pub controls: Bindings,
+    pub fire_mode: FireMode,
+    pub effects: EffectsLevel,
+    path: PathBuf,
+    file: ConfigFile,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    scale: Option<u32>,
+    fullscreen: Option<bool>,
+    lives: Option<u32>,
+    seed: Option<u64>,
+    font: Option<PathBuf>,
+    data_dir: Option<PathBuf>,
+    effects: Option<EffectsLevel>,
+    audio: Option<Volume>,
+    weapon: Option<WeaponTable>,
+    /// Action name to SDL key names.
+    controls: BTreeMap<String, Vec<String>>,
+}
+
+/// `mode` picks a preset; the other keys replace single values of it.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct WeaponTable {
+    mode: Option<String>,
+    max_bullets: Option<u32>,
+    cooldown_ms: Option<u32>,
+    bullet_speed: Option<u32>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Invalid { field: &'static str, msg: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "{}", e),
+            ConfigError::Parse(e) => write!(f, "{}", e),
+            ConfigError::Invalid { field, msg } => write!(f, "`{}` {}", field, msg),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the file named by `--config`, or `config.toml`, and applies the
+    /// rest of `args` on top. Only the default file may be missing.
+    pub fn load(args: &Args) -> Result<Self, (PathBuf, ConfigError)> {
+        let path = args.config.clone().unwrap_or_else(|| PathBuf::from(CONFIG_PATH));
+        let mut config = Self::read(&path, args.config.is_none()).map_err(|e| (path, e))?;
+        config.apply(args);
+        Ok(config)
+    }
+
+    fn read(path: &Path, optional: bool) -> Result<Self, ConfigError> {
+        let file: ConfigFile = match fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text).map_err(ConfigError::Parse)?,
+            Err(e) if optional && e.kind() == io::ErrorKind::NotFound => ConfigFile::default(),
+            Err(e) => return Err(ConfigError::Io(e)),
+        };
+
+        let invalid = |field, msg| ConfigError::Invalid { field, msg };
+        let scale = in_range(file.scale, SCALE, 1).map_err(|msg| invalid("scale", msg))?;
+        let lives = in_range(file.lives, LIVES, DEFAULT_LIVES).map_err(|msg| invalid("lives", msg))?;
+        let volume = file.audio.unwrap_or_default();
+        for (field, level) in [("audio.master", volume.master), ("audio.sfx", volume.sfx), ("audio.music", volume.music)] {
+            if level > 100 {
+                return Err(invalid(field, format!("must be at most 100, got {}", level)));
+            }
+        }
+        let controls = Bindings::from_names(&file.controls).map_err(|msg| invalid("controls", msg))?;
+
+        let weapon = file.weapon.clone().unwrap_or_default();
+        let fire_mode = match weapon.mode.as_deref() {
+            None => FireMode::Classic,
+            Some(name) => FireMode::ALL.into_iter().find(|m| m.name() == name).ok_or_else(|| {
+                let names: Vec<String> = FireMode::ALL.iter().map(|m| format!("\"{}\"", m.name())).collect();
+                invalid("weapon.mode", format!("must be one of {}, got \"{}\"", names.join(", "), name))
+            })?,
+        };
+        in_range(weapon.max_bullets, MAX_BULLETS, 1).map_err(|msg| invalid("weapon.max_bullets", msg))?;
+        in_range(weapon.cooldown_ms, COOLDOWN_MS, 0).map_err(|msg| invalid("weapon.cooldown_ms", msg))?;
+        in_range(weapon.bullet_speed, (1, MAX_BULLET_SPEED as u32), 1).map_err(|msg| invalid("weapon.bullet_speed", msg))?;
+
+        Ok(Self {
+            scale,
+            fullscreen: file.fullscreen.unwrap_or(false),
+            lives,
+            seed: file.seed,
+            font: file.font.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_FONT)),
+            data_dir: file.data_dir.clone(),
+            volume,
+            controls,
+            fire_mode,
+            effects: file.effects.unwrap_or_default(),
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    // Command line values were range checked when they were parsed.
+    fn apply(&mut self, args: &Args) {
+        if let Some(scale) = args.scale { self.scale = scale; }
+        if let Some(fullscreen) = args.fullscreen { self.fullscreen = fullscreen; }
+        if let Some(lives) = args.lives { self.lives = lives; }
+        if args.seed.is_some() { self.seed = args.seed; }
+        if args.data_dir.is_some() { self.data_dir = args.data_dir.clone(); }
+    }
+
+    /// The fire mode's preset with any values the file replaces.
+    pub fn weapon(&self) -> Weapon {
+        let mut weapon = self.fire_mode.weapon();
+        if let Some(table) = &self.file.weapon {
+            if let Some(n) = table.max_bullets { weapon.max_bullets = n; }
+            if let Some(ms) = table.cooldown_ms { weapon.cooldown = ticks_from_ms(ms as u64); }
+            if let Some(speed) = table.bullet_speed { weapon.bullet_speed = speed as i32; }
+        }
+        weapon
+    }
+
+    pub fn loadout(&self) -> Loadout {
+        Loadout { lives: self.lives, weapon: self.weapon() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the current controls, volume, fire mode and effects level back
+    /// to the file it was read from, leaving every other setting, comment and
+    /// blank line in it alone. The file is replaced atomically.
+    pub fn save(&self) -> io::Result<()> {
+        let mut doc = match fs::read_to_string(&self.path) {
+            Ok(text) => text.parse::<DocumentMut>().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => DocumentMut::new(),
+            Err(e) => return Err(e),
+        };
+
+        set(&mut doc["effects"], self.effects.name());
+        let audio = &mut doc["audio"];
+        set(&mut audio["master"], self.volume.master as i64);
+        set(&mut audio["sfx"], self.volume.sfx as i64);
+        set(&mut audio["music"], self.volume.music as i64);
+        set(&mut audio["muted"], self.volume.muted);
+        set(&mut doc["weapon"]["mode"], self.fire_mode.name());
+        for (action, keys) in self.controls.to_names() {
+            set(&mut doc["controls"][action.as_str()], keys.iter().collect::<toml_edit::Array>());
+        }
+
+        persist::write_atomic(&self.path, doc.to_string().as_bytes())
+    }
+}
+
+/// Replaces a value unless it is unchanged, keeping the comment around it.
+fn set(item: &mut Item, value: impl Into<Value>) {
+    let mut value = value.into();
+    if let Some(old) = item.as_value() {
+        if old.to_string().trim() == value.to_string().trim() {
+            return;
+        }
+        *value.decor_mut() = old.decor().clone();
+    }
+    *item = Item::Value(value);
+}
+
+fn in_range(value: Option<u32>, (lo, hi): (u32, u32), default: u32) -> Result<u32, String> {
+    match value {
+        None => Ok(default),
+        Some(v) if (lo..=hi).contains(&v) => Ok(v),
+        Some(v) => Err(format!("must be between {} and {}, got {}", lo, hi, v)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("config-test-{}-{}.toml", std::process::id(), name))
+    }
+
+    fn cleanup(path: &Path) {
+        for p in [path.to_path_buf(), persist::with_suffix(path, ".bak")] {
+            let _ = fs::remove_file(p);
+        }
+    }
+
+    /// Loads `file` as the config file with `args` on top.
+    fn load(name: &str, file: &str, mut args: Args) -> Result<Config, (PathBuf, ConfigError)> {
+        let path = temp_path(name);
+        fs::write(&path, file).unwrap();
+        args.config = Some(path.clone());
+        let config = Config::load(&args);
+        cleanup(&path);
+        config
+    }
+
+    #[test]
+    fn flags_win_over_the_file_and_the_file_over_defaults() {
+        let defaults = load("defaults", "", Args::default()).unwrap();
+        assert_eq!((defaults.lives, defaults.scale, defaults.seed), (DEFAULT_LIVES, 1, None));
+
+        let file = "lives = 5\nscale = 3\nseed = 7\n";
+        let from_file = load("file", file, Args::default()).unwrap();
+        assert_eq!((from_file.lives, from_file.scale, from_file.seed), (5, 3, Some(7)));
+
+        let args = Args { lives: Some(2), scale: Some(4), seed: Some(99), ..Args::default() };
+        let from_flags = load("flags", file, args).unwrap();
+        assert_eq!((from_flags.lives, from_flags.scale, from_flags.seed), (2, 4, Some(99)));
+
+        let args = Args { lives: Some(1), ..Args::default() };
+        let mixed = load("mixed", file, args).unwrap();
+        assert_eq!((mixed.lives, mixed.scale, mixed.seed), (1, 3, Some(7)));
+    }
+
+    #[test]
+    fn errors_name_the_file_and_key() {
+        let cases = [
+            ("scale", "scale = 9\n", "`scale` must be between 1 and 4, got 9"),
+            ("lives", "lives = 0\n", "`lives` must be between 1 and 9, got 0"),
+            ("volume", "[audio]\nmaster = 101\n", "`audio.master` must be at most 100, got 101"),
+            ("mode", "[weapon]\nmode = \"laser\"\n", "`weapon.mode` must be one of \"classic\", \"rapid\", got \"laser\""),
+        ];
+        for (name, file, msg) in cases {
+            let (path, e) = load(name, file, Args::default()).unwrap_err();
+            assert_eq!(path, temp_path(name));
+            assert_eq!(e.to_string(), msg);
+        }
+
+        let (path, e) = load("not-a-number", "lives = \"three\"\n", Args::default()).unwrap_err();
+        assert_eq!(path, temp_path("not-a-number"));
+        let msg = e.to_string();
+        assert!(matches!(e, ConfigError::Parse(_)));
+        assert!(msg.contains("line 1") && msg.contains("lives"), "{}", msg);
+    }
+
+    #[test]
+    fn only_the_default_file_may_be_missing() {
+        let path = temp_path("absent");
+        cleanup(&path);
+        let args = Args { config: Some(path.clone()), ..Args::default() };
+        let (at, e) = Config::load(&args).unwrap_err();
+        assert_eq!(at, path);
+        assert!(matches!(e, ConfigError::Io(_)));
+        assert!(Config::read(&path, true).is_ok());
+    }
+
+    #[test]
+    fn save_keeps_comments_and_other_settings() {
+        let path = temp_path("comments");
+        let text = "# my settings\nlives = 5 # more lives\n\n[audio]\n# quiet at night\nmaster = 80 # loud\nmuted = false\n";
+        fs::write(&path, text).unwrap();
+
+        let mut config = Config::read(&path, false).unwrap();
+        config.volume.master = 40;
+        config.fire_mode = FireMode::Rapid;
+        config.save().unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        let reread = Config::read(&path, false).unwrap();
+        let tmp_left = persist::with_suffix(&path, ".tmp").exists();
+        cleanup(&path);
+
+        for kept in ["# my settings\n", "lives = 5 # more lives\n", "# quiet at night\n", "master = 40 # loud\n", "muted = false\n"] {
+            assert!(saved.contains(kept), "{:?} missing from\n{}", kept, saved);
+        }
+        assert_eq!(reread.lives, 5);
+        assert_eq!(reread.volume.master, 40);
+        assert_eq!(reread.fire_mode, FireMode::Rapid);
+        assert_eq!(reread.controls, config.controls);
+        assert!(!tmp_left);
+    }
+
+    #[test]
+    fn save_creates_a_missing_file() {
+        let path = temp_path("missing");
+        cleanup(&path);
+        let mut config = Config::read(&path, true).unwrap();
+        config.effects = EffectsLevel::Off;
+        config.save().unwrap();
+        let reread = Config::read(&path, false).unwrap();
+        cleanup(&path);
+
+        assert_eq!(reread.effects, EffectsLevel::Off);
+        assert_eq!(reread.lives, DEFAULT_LIVES);
+    }
+}
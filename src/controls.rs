@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+
+use sdl2::{
+    keyboard::{
+        KeyboardState,
+        Scancode,
+    },
+    pixels::Color,
+    render::Canvas,
+    video::Window,
+};
+
+use crate::{
+    text::{
+        FontSize,
+        Position,
+        TextCache,
+    },
+    world::Input,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Fire,
+    Pause,
+    Confirm,
+    Back,
+}
+
+impl Action {
+    pub const ALL: [Action; 6] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Fire,
+        Action::Pause,
+        Action::Confirm,
+        Action::Back,
+    ];
+
+    /// Name used in the config file.
+    pub fn key(self) -> &'static str {
+        match self {
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::Fire => "fire",
+            Action::Pause => "pause",
+            Action::Confirm => "confirm",
+            Action::Back => "back",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::Fire => "Fire",
+            Action::Pause => "Pause",
+            Action::Confirm => "Confirm",
+            Action::Back => "Back",
+        }
+    }
+
+    fn index(self) -> usize {
+        Action::ALL.iter().position(|&a| a == self).expect("every action is in ALL")
+    }
+}
+
+/// Keys bound to each action. A key belongs to at most one action.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bindings {
+    keys: [Vec<Scancode>; 6],
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self {
+            keys: [
+                vec![Scancode::A, Scancode::Left],
+                vec![Scancode::D, Scancode::Right],
+                vec![Scancode::Space],
+                vec![Scancode::P],
+                vec![Scancode::Return, Scancode::KpEnter],
+                vec![Scancode::Escape],
+            ],
+        }
+    }
+}
+
+impl Bindings {
+    /// Defaults, overridden by the actions listed in `names` (action name to
+    /// SDL key names, e.g. `fire = ["Space", "Left Ctrl"]`).
+    pub fn from_names(names: &BTreeMap<String, Vec<String>>) -> Result<Self, String> {
+        let mut bindings = Self::default();
+        for (action_name, key_names) in names {
+            let action = Action::ALL.iter().copied().find(|a| a.key() == action_name)
+                .ok_or_else(|| format!("unknown action \"{}\" (have {})", action_name,
+                    Action::ALL.map(|a| a.key()).join(", ")))?;
+            if key_names.is_empty() {
+                return Err(format!("`{}` needs at least one key", action_name));
+            }
+            let keys = key_names.iter()
+                .map(|k| Scancode::from_name(k).ok_or_else(|| format!("`{}`: unknown key \"{}\"", action_name, k)))
+                .collect::<Result<Vec<_>, _>>()?;
+            bindings.keys[action.index()] = keys;
+        }
+        for (i, keys) in bindings.keys.iter().enumerate() {
+            for key in keys {
+                if let Some(other) = bindings.action(*key).filter(|a| a.index() != i) {
+                    return Err(format!("\"{}\" is bound to both `{}` and `{}`", key.name(), Action::ALL[i].key(), other.key()));
+                }
+            }
+        }
+        Ok(bindings)
+    }
+
+    pub fn to_names(&self) -> BTreeMap<String, Vec<String>> {
+        Action::ALL.iter()
+            .map(|a| (a.key().to_string(), self.keys(*a).iter().map(|k| k.name().to_string()).collect()))
+            .collect()
+    }
+
+    pub fn keys(&self, action: Action) -> &[Scancode] {
+        &self.keys[action.index()]
+    }
+
+    pub fn action(&self, key: Scancode) -> Option<Action> {
+        Action::ALL.iter().copied().find(|a| self.keys(*a).contains(&key))
+    }
+
+    pub fn held(&self, state: &KeyboardState, action: Action) -> bool {
+        self.keys(action).iter().any(|&k| state.is_scancode_pressed(k))
+    }
+
+    /// Makes `key` the only key of `action`. Fails with the action that owns
+    /// `key` if taking it away would leave that action unbound.
+    pub fn bind(&mut self, action: Action, key: Scancode) -> Result<(), Action> {
+        if let Some(owner) = self.action(key)
+            && owner != action
+        {
+            if self.keys(owner).len() == 1 {
+                return Err(owner);
+            }
+            self.keys[owner.index()].retain(|&k| k != key);
+        }
+        self.keys[action.index()] = vec![key];
+        Ok(())
+    }
+}
+
+/// Builds the world input from whichever devices report an action as held.
+pub fn input(held: impl Fn(Action) -> bool) -> Input {
+    Input {
+        left: held(Action::MoveLeft),
+        right: held(Action::MoveRight),
+        fire: held(Action::Fire),
+    }
+}
+
+pub enum MenuOutcome {
+    Stay,
+    /// Bindings were changed and should be saved.
+    Changed,
+    Leave,
+}
+
+/// The "Controls" screen: one row per action plus "Reset defaults". Confirm
+/// on a row waits for the next key press and binds it; Escape cancels.
+#[derive(Default)]
+pub struct ControlsMenu {
+    selected: usize,
+    capturing: bool,
+    message: Option<String>,
+}
+
+const RESET_ROW: usize = Action::ALL.len();
+
+impl ControlsMenu {
+    pub fn key_down(&mut self, key: Scancode, bindings: &mut Bindings) -> MenuOutcome {
+        if self.capturing {
+            self.capturing = false;
+            if key == Scancode::Escape {
+                self.message = None;
+                return MenuOutcome::Stay;
+            }
+            let action = Action::ALL[self.selected];
+            return match bindings.bind(action, key) {
+                Ok(()) => {
+                    self.message = None;
+                    MenuOutcome::Changed
+                }
+                Err(owner) => {
+                    self.message = Some(format!("{} is the only {} key", key.name(), owner.label()));
+                    MenuOutcome::Stay
+                }
+            };
+        }
+
+        self.navigate(Some(key), bindings.action(key), bindings)
+    }
+
+    /// A game controller press. The pad moves between rows and confirms like
+    /// the keyboard, but only a key can be bound, so while waiting for one
+    /// `Back` cancels and anything else is ignored.
+    pub fn pad_press(&mut self, key: Option<Scancode>, action: Option<Action>, bindings: &mut Bindings) -> MenuOutcome {
+        if self.capturing {
+            if action == Some(Action::Back) {
+                self.capturing = false;
+                self.message = None;
+            }
+            return MenuOutcome::Stay;
+        }
+        self.navigate(key, action, bindings)
+    }
+
+    fn navigate(&mut self, key: Option<Scancode>, action: Option<Action>, bindings: &mut Bindings) -> MenuOutcome {
+        match (key, action) {
+            (Some(Scancode::Up), _) => {
+                self.selected = (self.selected + RESET_ROW) % (RESET_ROW + 1);
+            }
+            (Some(Scancode::Down), _) => {
+                self.selected = (self.selected + 1) % (RESET_ROW + 1);
+            }
+            (_, Some(Action::Confirm)) if self.selected == RESET_ROW => {
+                *bindings = Bindings::default();
+                self.message = Some("Defaults restored".to_string());
+                return MenuOutcome::Changed;
+            }
+            (_, Some(Action::Confirm)) => {
+                self.capturing = true;
+                self.message = Some("Press a key, Esc to cancel".to_string());
+            }
+            (_, Some(Action::Back)) => return MenuOutcome::Leave,
+            _ => {}
+        }
+        MenuOutcome::Stay
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, text: &mut TextCache, bindings: &Bindings) {
+        text.draw(canvas, FontSize::Big, "Controls", Position::BelowCenter(-220));
+
+        let highlight = Color::RGB(255, 220, 60);
+        for row in 0..=RESET_ROW {
+            let line = match Action::ALL.get(row) {
+                Some(&action) if self.capturing && row == self.selected => format!("{:<11} ...", action.label()),
+                Some(&action) => {
+                    let keys: Vec<&str> = bindings.keys(action).iter().map(|k| k.name()).collect();
+                    format!("{:<11} {}", action.label(), keys.join(", "))
+                }
+                None => "Reset defaults".to_string(),
+            };
+            let color = if row == self.selected { highlight } else { Color::WHITE };
+            text.draw_colored(canvas, FontSize::Small, &line, color, Position::BelowCenter(-140 + row as i32 * 35));
+        }
+
+        if let Some(message) = &self.message {
+            text.draw(canvas, FontSize::Small, message, Position::BelowCenter(140));
+        }
+        let first = |action| bindings.keys(action).first().map(|k| k.name()).unwrap_or("-");
+        let hint = format!("Up/Down  {} rebind  {} back", first(Action::Confirm), first(Action::Back));
+        text.draw(canvas, FontSize::Small, &hint, Position::BottomLeft);
+    }
+}
@@ -0,0 +1,52 @@
+//! The window and how the playfield is fitted into it. Everything is drawn
+//! in `WINDOW_W` x `WINDOW_H` logical pixels; SDL scales that to the window
+//! and letterboxes whatever is left over.
+
+use sdl2::{
+    render::Canvas,
+    video::{
+        FullscreenType,
+        Window,
+    },
+    VideoSubsystem,
+};
+
+use crate::world::{
+    WINDOW_H,
+    WINDOW_W,
+};
+
+pub fn create_canvas(video: &VideoSubsystem, scale: u32, fullscreen: bool) -> Result<Canvas<Window>, String> {
+    let mut window = video.window("space-invade-rs", WINDOW_W as u32 * scale, WINDOW_H as u32 * scale);
+    window.position_centered().resizable();
+    if fullscreen {
+        window.fullscreen_desktop();
+    }
+    let window = window.build().map_err(|e| e.to_string())?;
+
+    let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+    canvas.set_logical_size(WINDOW_W as u32, WINDOW_H as u32).map_err(|e| e.to_string())?;
+    fit(&mut canvas);
+    Ok(canvas)
+}
+
+/// Call after the window changes size. Whole-number scaling keeps sprite
+/// cells square, but SDL crops instead of shrinking when the window is
+/// smaller than the playfield, so it is only used when the playfield fits.
+pub fn fit(canvas: &mut Canvas<Window>) {
+    let (w, h) = canvas.output_size().unwrap_or((0, 0));
+    let fits = w >= WINDOW_W as u32 && h >= WINDOW_H as u32;
+    let _ = canvas.set_integer_scale(fits);
+}
+
+pub fn toggle_fullscreen(canvas: &mut Canvas<Window>) {
+    let window = canvas.window_mut();
+    let next = match window.fullscreen_state() {
+        FullscreenType::Off => FullscreenType::Desktop,
+        _ => FullscreenType::Off,
+    };
+    if let Err(e) = window.set_fullscreen(next) {
+        eprintln!("cannot change fullscreen mode: {}", e);
+    }
+    fit(canvas);
+}
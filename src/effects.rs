@@ -0,0 +1,230 @@
+//! Explosions, debris and score pop-ups. They are spawned from the world's
+//! events and kept in their own pool outside the world, so they never change
+//! how a run plays out and can be turned down or off.
+
+use std::rc::Rc;
+
+use sdl2::{
+    pixels::Color,
+    rect::Rect,
+    render::Canvas,
+    video::Window,
+};
+use serde::Deserialize;
+
+use crate::{
+    clock::ticks_from_ms,
+    render::SpriteTextures,
+    rng::Rng,
+    sprites::{
+        SpriteBank,
+        SpriteDef,
+        SpriteError,
+    },
+    text::{
+        FontSize,
+        Position,
+        TextCache,
+    },
+    world::{
+        GameEvent,
+        Impact,
+        PIXEL,
+        WINDOW_H,
+        WINDOW_W,
+    },
+};
+
+/// Most effects alive at once; anything spawned past this is skipped.
+const MAX_EFFECTS: usize = 256;
+
+const EXPLOSION_TICKS: u64 = ticks_from_ms(300);
+const SPARK_TICKS: u64 = ticks_from_ms(150);
+const DEBRIS_TICKS: u64 = ticks_from_ms(600);
+const POPUP_TICKS: u64 = ticks_from_ms(1000);
+
+/// Pieces of debris a kill throws out at `Full`; `Reduced` throws a third.
+const DEBRIS_PIECES: usize = 12;
+/// Downward pull on debris, in pixels per tick per tick.
+const GRAVITY: f32 = 0.15;
+/// Pixels per tick a pop-up rises.
+const POPUP_RISE: f32 = 0.5;
+const MOTHERSHIP_POPUP: [u8; 3] = [255, 60, 60];
+
+/// How much of the effects to show, set in the options.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EffectsLevel {
+    #[default]
+    Full,
+    /// Explosions, a little debris and the mothership's score.
+    Reduced,
+    /// Only the mothership's score, which the player needs to see.
+    Off,
+}
+
+impl EffectsLevel {
+    pub const ALL: [EffectsLevel; 3] = [EffectsLevel::Full, EffectsLevel::Reduced, EffectsLevel::Off];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EffectsLevel::Full => "full",
+            EffectsLevel::Reduced => "reduced",
+            EffectsLevel::Off => "off",
+        }
+    }
+}
+
+enum Kind {
+    /// Plays through the sprite's frames over its lifetime.
+    Sprite(Rc<SpriteDef>),
+    Debris { vx: f32, vy: f32, color: [u8; 3] },
+    PopUp { text: String, color: [u8; 3] },
+}
+
+/// One effect centred on `(x, y)`.
+struct Effect {
+    kind: Kind,
+    x: f32,
+    y: f32,
+    age: u64,
+    ttl: u64,
+}
+
+pub struct EffectPool {
+    level: EffectsLevel,
+    explosion: Rc<SpriteDef>,
+    spark: Rc<SpriteDef>,
+    effects: Vec<Effect>,
+    // Separate from the world's so effects never shift its random numbers.
+    rng: Rng,
+}
+
+impl EffectPool {
+    pub fn new(sprites: &SpriteBank, level: EffectsLevel) -> Result<Self, SpriteError> {
+        Ok(Self {
+            level,
+            explosion: sprites.require("explosion")?.clone(),
+            spark: sprites.require("spark")?.clone(),
+            effects: Vec::with_capacity(MAX_EFFECTS),
+            rng: Rng::new(0x0EFF_EC75),
+        })
+    }
+
+    /// Applies to effects spawned from now on.
+    pub fn set_level(&mut self, level: EffectsLevel) {
+        self.level = level;
+    }
+
+    pub fn clear(&mut self) {
+        self.effects.clear();
+    }
+
+    /// Spawns whatever a world step's events call for, then moves every
+    /// effect on by one tick. Call once per step.
+    pub fn step(&mut self, events: &[GameEvent]) {
+        for &event in events {
+            self.spawn(event);
+        }
+
+        for e in self.effects.iter_mut() {
+            e.age += 1;
+            match &mut e.kind {
+                Kind::Debris { vx, vy, .. } => {
+                    e.x += *vx;
+                    e.y += *vy;
+                    *vy += GRAVITY;
+                }
+                Kind::PopUp { .. } => e.y -= POPUP_RISE,
+                Kind::Sprite(_) => {}
+            }
+        }
+        self.effects.retain(|e| e.age < e.ttl);
+    }
+
+    fn spawn(&mut self, event: GameEvent) {
+        let debris = match self.level {
+            EffectsLevel::Full => DEBRIS_PIECES,
+            EffectsLevel::Reduced => DEBRIS_PIECES / 3,
+            EffectsLevel::Off => 0,
+        };
+        let shown = self.level != EffectsLevel::Off;
+
+        match event {
+            GameEvent::AlienKilled(at, score) => {
+                if shown {
+                    self.sprite(self.explosion.clone(), at, EXPLOSION_TICKS);
+                }
+                self.debris(at, debris);
+                if self.level == EffectsLevel::Full {
+                    self.popup(at, score, at.color);
+                }
+            }
+            GameEvent::MothershipKilled(at, value) => {
+                if shown {
+                    self.sprite(self.explosion.clone(), at, EXPLOSION_TICKS);
+                }
+                self.debris(at, debris * 2);
+                self.popup(at, value, MOTHERSHIP_POPUP);
+            }
+            GameEvent::AlienHit(at) | GameEvent::PlayerHit(at) => self.debris(at, debris / 2),
+            GameEvent::ShieldHit(at) | GameEvent::ShotsCollided(at) => {
+                if shown {
+                    self.sprite(self.spark.clone(), at, SPARK_TICKS);
+                }
+            }
+            GameEvent::FleetStepped
+            | GameEvent::PlayerFired
+            | GameEvent::PowerUpCollected(_)
+            | GameEvent::WaveCleared
+            | GameEvent::GameOver(_) => {}
+        }
+    }
+
+    fn push(&mut self, kind: Kind, at: Impact, ttl: u64) {
+        if self.effects.len() < MAX_EFFECTS {
+            self.effects.push(Effect { kind, x: at.x as f32, y: at.y as f32, age: 0, ttl });
+        }
+    }
+
+    fn sprite(&mut self, def: Rc<SpriteDef>, at: Impact, ttl: u64) {
+        self.push(Kind::Sprite(def), at, ttl);
+    }
+
+    /// `pieces` cells of `at.color` thrown up and out, falling back down.
+    fn debris(&mut self, at: Impact, pieces: usize) {
+        for _ in 0..pieces {
+            let vx = self.rng.range(0, 400) as f32 / 100.0 - 2.0;
+            let vy = -(self.rng.range(50, 300) as f32 / 100.0);
+            self.push(Kind::Debris { vx, vy, color: at.color }, at, DEBRIS_TICKS);
+        }
+    }
+
+    fn popup(&mut self, at: Impact, points: i32, color: [u8; 3]) {
+        self.push(Kind::PopUp { text: points.to_string(), color }, at, POPUP_TICKS);
+    }
+
+    /// Meant to be called after the world is drawn, so effects sit on top.
+    pub fn draw(&self, canvas: &mut Canvas<Window>, textures: &SpriteTextures, text: &mut TextCache) {
+        for e in &self.effects {
+            let (x, y) = (e.x.round() as i32, e.y.round() as i32);
+            match &e.kind {
+                Kind::Sprite(def) => {
+                    let frames = def.frames.len() as u64;
+                    let frame = (e.age * frames / e.ttl).min(frames - 1) as usize;
+                    let (w, h) = def.cells();
+                    textures.draw(canvas, def, frame, x - w * PIXEL as i32 / 2, y - h * PIXEL as i32 / 2);
+                }
+                Kind::Debris { color: [r, g, b], .. } => {
+                    canvas.set_draw_color(Color::RGB(*r, *g, *b));
+                    let size = PIXEL - 1;
+                    let _ = canvas.fill_rect(Rect::new(x - size as i32 / 2, y - size as i32 / 2, size, size));
+                }
+                Kind::PopUp { text: label, color: [r, g, b] } => {
+                    let at = Position::CenterOffset(x - WINDOW_W / 2, y - WINDOW_H / 2);
+                    text.draw_colored(canvas, FontSize::Small, label, Color::RGB(*r, *g, *b), at);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use sdl2::{
+    controller::{
+        Axis,
+        Button,
+        GameController,
+    },
+    event::Event,
+    keyboard::Scancode,
+    GameControllerSubsystem,
+    Sdl,
+};
+
+use crate::controls::Action;
+
+/// Left stick travel ignored around the centre, out of 32767.
+const STICK_DEADZONE: i16 = 8000;
+
+/// Button presses handled like key presses. Movement and firing are read
+/// from the held state instead, see `Gamepads::held`.
+const PRESSES: [(Button, Action); 3] = [
+    (Button::A, Action::Confirm),
+    (Button::Start, Action::Pause),
+    (Button::Back, Action::Back),
+];
+
+/// D-pad buttons sent to the menus as the arrow keys they stand for.
+const ARROWS: [(Button, Scancode); 4] = [
+    (Button::DPadUp, Scancode::Up),
+    (Button::DPadDown, Scancode::Down),
+    (Button::DPadLeft, Scancode::Left),
+    (Button::DPadRight, Scancode::Right),
+];
+
+/// A press as the event loop dispatches it: the key, if it stands for one,
+/// and the action.
+pub type Press = (Option<Scancode>, Option<Action>);
+
+struct Pad {
+    controller: GameController,
+    /// Left stick direction on each axis (x, y) as -1, 0 or 1, so pushing it
+    /// counts as one press until it goes back to the centre.
+    stick: [i8; 2],
+}
+
+/// Every connected game controller; they all drive the same player. SDL
+/// reports controllers present at startup as added too, so both cases go
+/// through `handle`.
+pub struct Gamepads {
+    subsystem: Option<GameControllerSubsystem>,
+    pads: Vec<Pad>,
+}
+
+impl Gamepads {
+    /// Without the controller subsystem the game is keyboard only.
+    pub fn new(sdl: &Sdl) -> Self {
+        Self { subsystem: sdl.game_controller().ok(), pads: Vec::new() }
+    }
+
+    /// Opens and closes controllers as they are plugged in and out and turns
+    /// button presses into actions. The D-pad and the left stick also move
+    /// through menus like the arrow keys.
+    pub fn handle(&mut self, event: &Event) -> Option<Press> {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                let subsystem = self.subsystem.as_ref()?;
+                if subsystem.is_game_controller(which) {
+                    match subsystem.open(which) {
+                        Ok(controller) => {
+                            eprintln!("controller connected: {}", controller.name());
+                            self.pads.push(Pad { controller, stick: [0, 0] });
+                        }
+                        Err(e) => eprintln!("cannot open controller {}: {}", which, e),
+                    }
+                }
+                None
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.pads.retain(|p| p.controller.instance_id() != which);
+                None
+            }
+            Event::ControllerButtonDown { button, .. } => {
+                let arrow = ARROWS.iter().find(|(b, _)| *b == button).map(|(_, key)| *key);
+                let action = PRESSES.iter().find(|(b, _)| *b == button).map(|(_, action)| *action);
+                (arrow.is_some() || action.is_some()).then_some((arrow, action))
+            }
+            Event::ControllerAxisMotion { which, axis, value, .. } => {
+                let ix = match axis {
+                    Axis::LeftX => 0,
+                    Axis::LeftY => 1,
+                    _ => return None,
+                };
+                let pad = self.pads.iter_mut().find(|p| p.controller.instance_id() == which)?;
+                let dir = if value < -STICK_DEADZONE { -1 } else if value > STICK_DEADZONE { 1 } else { 0 };
+                if pad.stick[ix] == dir {
+                    return None;
+                }
+                pad.stick[ix] = dir;
+                let key = match (ix, dir) {
+                    (0, -1) => Scancode::Left,
+                    (0, 1) => Scancode::Right,
+                    (1, -1) => Scancode::Up,
+                    (1, 1) => Scancode::Down,
+                    _ => return None,
+                };
+                Some((Some(key), None))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn held(&self, action: Action) -> bool {
+        self.pads.iter().map(|p| &p.controller).any(|pad| match action {
+            Action::MoveLeft => pad.button(Button::DPadLeft) || pad.axis(Axis::LeftX) < -STICK_DEADZONE,
+            Action::MoveRight => pad.button(Button::DPadRight) || pad.axis(Axis::LeftX) > STICK_DEADZONE,
+            Action::Fire => pad.button(Button::A),
+            Action::Pause | Action::Confirm | Action::Back => false,
+        })
+    }
+}
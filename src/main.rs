@@ -1,549 +1,414 @@
 extern crate sdl2;
 
+mod audio;
+mod bench;
+mod cli;
+mod clock;
+mod config;
+mod controls;
+mod display;
+mod effects;
+mod gamepad;
+mod options;
+mod persist;
+mod powerups;
+mod render;
+mod replay;
+mod rng;
+mod scores;
+mod sprites;
+mod text;
+mod waves;
+mod world;
+
 use sdl2::{
     pixels::Color,
-    event::Event,
+    event::{
+        Event,
+        WindowEvent,
+    },
     keyboard::{
+        Mod,
         Scancode,
-        Keycode,
     },
-    rect::Rect,
-    render::TextureQuery,
 };
 
 use std::{
-    fs,
-    rc::Rc,
-    time::{
-        Duration,
-        Instant,
-    }
+    path::{
+        Path,
+        PathBuf,
+    },
+    time::Duration,
+};
+
+use audio::Audio;
+use clock::FixedClock;
+use config::Config;
+use controls::{
+    Action,
+    ControlsMenu,
+    MenuOutcome,
+};
+use effects::EffectPool;
+use gamepad::Gamepads;
+use options::{
+    OptionsMenu,
+    OptionsOutcome,
+};
+use render::SpriteTextures;
+use replay::Replay;
+use scores::{
+    InitialsEntry,
+    ScoreTable,
+};
+use sprites::{
+    SpriteBank,
+    SpriteDef,
+};
+use text::{
+    FontSize,
+    Position,
+    TextCache,
+};
+use world::{
+    GameEvent,
+    LossReason,
+    World,
+    PIXEL,
+    WINDOW_W,
 };
 
-const PIXEL: u32 = 5;
-const WINDOW_W: i32 = 800;
-const WINDOW_H: i32 = 600;
-const HIGHSCORE_PATH: &str = "assets/highscore.txt";
+const REPLAY_DIR: &str = "replays";
+const REPLAY_FAST_FORWARD: u32 = 4;
+const WAVE_INTRO_TICKS: u64 = clock::TICK_RATE * 2;
 
 enum GameState {
     TitleScreen,
+    WaveIntro,
     Playing,
     Pause,
     Lost,
+    EnterInitials,
+    Options,
+    Controls,
+    Replay,
 }
 
-#[derive(Clone)]
-struct Player {
-    sprite: Vec<Vec<i32>>,
-    x: i32,
-    y: i32,
-    lives: i32,
-}
-
-impl Player {
-    fn new(sprite: Vec<Vec<i32>>, x: i32, y: i32, lives: i32) -> Self {
-        Self {
-            sprite,
-            x,
-            y,
-            lives,
-        }
+/// Loads a recorded run to watch instead of playing. `data_hash` is that of
+/// the sprites and waves loaded now.
+fn load_replay(path: &Path, data_hash: u64) -> Result<Replay, String> {
+    let replay = Replay::load(path)
+        .map_err(|e| format!("cannot load replay {}: {}", path.display(), e))?;
+    if replay.game_version != env!("CARGO_PKG_VERSION") {
+        eprintln!(
+            "warning: replay was recorded with version {}, this is {}; playback may diverge",
+            replay.game_version,
+            env!("CARGO_PKG_VERSION"),
+        );
     }
-    fn rect(&self) -> Rect {
-        let w = (self.sprite.get(0).map(|r| r.len()).unwrap_or(0) as u32) * PIXEL;
-        let h = (self.sprite.len() as u32) * PIXEL;
-        Rect::new(self.x, self.y, w, h)
+    if replay.data_hash.is_some_and(|h| h != data_hash) {
+        eprintln!("warning: replay was recorded with different sprites or waves; playback may diverge");
     }
+    Ok(replay)
 }
 
-#[derive(Clone)]
-struct Shield {
-    sprite: Vec<Vec<i32>>,
-    x: i32,
-    y: i32,
-    hp: i32,
-    alive: bool,
-}
-
-impl Shield {
-    fn new(sprite: Vec<Vec<i32>>, x: i32, y: i32, hp: i32) -> Self {
-        Self { sprite, x, y, hp, alive: true }
-    }
-
-    fn w(&self) -> i32 { 
-        (self.sprite.get(0).map(|r| r.len()).unwrap_or(0) as i32) * PIXEL as i32 
-    }
-    
-    fn h(&self) -> i32 {
-        (self.sprite.len() as i32) * PIXEL as i32
-    }
-    
-    fn rect(&self) -> Rect { 
-        Rect::new(self.x, self.y, self.w() as u32, self.h() as u32) 
-    }
-
-    fn draw(&self, canvas: &mut sdl2::render::Canvas<sdl2::video::Window>) {
-        if !self.alive { return; }
-        drawing(canvas, &self.sprite, self.x, self.y);
+/// Saves under `dir`, named after the current second.
+fn save_replay(replay: &Replay, dir: &Path) {
+    match replay.save_new(dir, rng::seed_from_time() / 1_000_000_000) {
+        Ok(path) => println!("replay saved to {}", path.display()),
+        Err(e) => eprintln!("cannot save replay in {}: {}", dir.display(), e),
     }
 }
 
-#[derive(Clone)]
-struct Alien {
-    // sprite: Vec<Vec<i32>>,
-    frames: Rc<Vec<Vec<Vec<i32>>>>,
-    frame_ix: usize,
-    frame_interval: Duration,
-    last_frame: Instant,
-
-    x: i32,
-    y: i32,
-    alive: bool,
-}
-
-impl Alien {
-    fn new(frames: Rc<Vec<Vec<Vec<i32>>>>, x: i32, y: i32) -> Self {
-        Self {
-            // sprite,
-            frames,
-            frame_ix: 0,
-            frame_interval: Duration::from_millis(800),
-            last_frame: Instant::now(),
-            x,
-            y,
-            alive: true,
-        }
-    }
-
-    fn current_sprite(&self) -> &Vec<Vec<i32>> {
-        &self.frames[self.frame_ix]
-    }
-
-    fn update_animation(&mut self) {
-        if self.last_frame.elapsed() >= self.frame_interval {
-            self.frame_ix = (self.frame_ix + 1) % self.frames.len();
-            self.last_frame = Instant::now();
-        }
-    }
-
-    fn w(&self) -> i32 {
-        (self.current_sprite().get(0).map(|r| r.len()).unwrap_or(0) as i32) * PIXEL as i32
-    }
-
-    fn h(&self) -> i32 {
-        (self.current_sprite().len() as i32) * PIXEL as i32
-    }
-
-    fn translate(&mut self, dx: i32, dy: i32) {
-        self.x += dx;
-        self.y += dy;
-    }
-
-    fn draw(&self, canvas: &mut sdl2::render::Canvas<sdl2::video::Window>) {
-        if !self.alive { return; }
-        drawing(canvas, &self.current_sprite(), self.x, self.y)
+fn draw_hud(
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    text: &mut TextCache,
+    textures: &SpriteTextures,
+    world: &World,
+    heart: &SpriteDef,
+) {
+    let score_text = format!("Score: {}", world.score);
+    text.draw(canvas, FontSize::Small, &score_text, Position::TopLeft);
+    // let exit_text: &str = "Escape to exit";
+    // text.draw(canvas, FontSize::Small, exit_text, Position::TopRight);
+
+    // Timed power-ups under the score, with the seconds each has left.
+    for (i, (kind, ticks)) in world.player.effects.remaining(world.tick()).enumerate() {
+        let line = format!("{} {}", kind.label(), ticks.div_ceil(clock::TICK_RATE));
+        text.draw_colored(canvas, FontSize::Small, &line, Color::RGB(255, 220, 60), Position::At(10, 40 + i as i32 * 22));
     }
 
-    fn rect(&self) -> Rect {
-        let w = (self.current_sprite().get(0).map(|r| r.len()).unwrap_or(0) as u32) * PIXEL;
-        let h = (self.current_sprite().len() as u32) * PIXEL;
-        Rect::new(self.x, self.y, w, h)
+    let h_w = heart.cells().0 * PIXEL as i32;
+    let mut h_x = WINDOW_W - h_w - 10;
+    let h_y = 15;
+    for _ in 0..world.player.lives {
+        textures.draw(canvas, heart, 0, h_x, h_y);
+        h_x -= h_w + 5;
     }
 }
 
-fn spawner_grid(
-    origin: (i32, i32),
-    rows: u32,
-    cols: u32,
-    // sprite: &Vec<Vec<i32>>,
-    frames: Rc<Vec<Vec<Vec<i32>>>>,
-) -> Vec<Alien> {
-    let (ox, oy) = origin;
-
-    let first = &frames[0];
-    let sprite_h = first.len() as i32;
-    let sprite_w = first.get(0).map(|r| r.len()).unwrap_or(0) as i32;
-
-    let cell_w = (sprite_w + 4) * PIXEL as i32; 
-    let cell_h = (sprite_h + 6) * PIXEL as i32;
-
-    (0..rows)
-        .flat_map(|r| {
-            let f = frames.clone();
-            (0..cols).map(move |c| {
-                Alien::new(
-                    f.clone(),
-                    ox + c as i32 * cell_w, 
-                    oy + r as i32 * cell_h, 
-                )
-            })
-        })
-        .collect()
+fn exit_with(code: i32, msg: impl std::fmt::Display) -> ! {
+    eprintln!("{}", msg);
+    std::process::exit(code)
 }
 
-fn fleet_manager(aliens: &[Alien]) -> Option<(i32, i32, i32)> {
-    let mut min_x = 0;
-    let mut max_x = 0;
-    let mut max_y = 0;
-    let mut first = true;
-
-    for a in aliens.iter().filter(|a| a.alive) {
-        let (x, y, w, h) = (a.x, a.y, a.w(), a.h());
-        if first {
-            min_x = x;
-            max_x = x + w;
-            max_y = y + h;
-            first = false;
-        } else {
-            if x < min_x { min_x = x; }
-            if x + w > max_x { max_x = x + w; }
-            if y + h > max_y { max_y = y + h; }
-        }
-    }
-    if first { None } else { Some((min_x, max_x, max_y)) }
+/// Per-user directory for saved data, as chosen by SDL for this platform.
+fn default_data_dir() -> PathBuf {
+    sdl2::filesystem::pref_path("", "space-invade-rs")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("."))
 }
 
-fn wave(
-    alien_1_a: &Vec<Vec<i32>>, 
-    alien_1_b: &Vec<Vec<i32>>, 
-    alien_2_a: &Vec<Vec<i32>>, 
-    alien_2_b: &Vec<Vec<i32>>,
-    level: u32,
-) -> Vec<Alien> {
-    let mut aliens = Vec::new();
-
-    let (rows_top, rows_mid) = match level % 3 {
-        1 => (1, 3),
-        2 => (2, 2),
-        _ => (1, 4),
-    };
-
-    let cols: i32 = 12;
-    let origin_x = WINDOW_W / cols;
-
-    let alien_1_frames = Rc::new(vec![alien_1_a.clone(), alien_1_b.clone()]);
-    let alien_2_frames = Rc::new(vec![alien_2_a.clone(), alien_2_b.clone()]);
-
-    aliens.extend(spawner_grid((origin_x, WINDOW_H * 2 / 10), rows_top as u32, cols as u32, alien_1_frames));
-    aliens.extend(spawner_grid((origin_x, WINDOW_H * (2 + rows_top) / 10), rows_mid as u32, cols as u32, alien_2_frames));
-    aliens
+/// `path` is `None` when the table could not be loaded; the file is then
+/// left untouched so the record is not overwritten.
+fn save_scores(scores: &ScoreTable, path: Option<&Path>) {
+    let Some(path) = path else { return };
+    if let Err(e) = scores.save(path) {
+        eprintln!("cannot save {}: {}", path.display(), e);
+    }
 }
 
-struct Bullet {
-    x: i32,
-    y: i32,
-    vy: i32,
-    w: u32,
-    h: u32,
-    alive: bool,
+fn save_config(config: &Config) {
+    if let Err(e) = config.save() {
+        eprintln!("cannot save {}: {}", config.path().display(), e);
+    }
 }
 
-impl Bullet {
-    fn new(x:i32, y: i32, vy: i32) -> Self {
-        Self { x, y, vy, w: PIXEL, h: PIXEL * 2, alive: true }
+pub fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let args = cli::parse(&args).unwrap_or_else(|e| exit_with(2, e));
+    if args.help {
+        println!("{}", cli::USAGE);
+        return;
     }
-
-    fn update(&mut self) {
-        self.y += self.vy;
-        if self.y + self.h as i32 <= 0 || self.y >= WINDOW_H { 
-            self.alive = false; 
+    let mut config = Config::load(&args)
+        .unwrap_or_else(|(path, e)| exit_with(2, format!("{}: {}", path.display(), e)));
+
+    let data_dir = config.data_dir.clone().unwrap_or_else(default_data_dir);
+    let replay_dir = data_dir.join(REPLAY_DIR);
+    let scores_file = data_dir.join(scores::SCORES_FILE);
+    let first_run = !scores_file.exists();
+    let (mut scores, scores_path) = match ScoreTable::load(&scores_file) {
+        Ok((table, None)) => (table, Some(scores_file)),
+        Ok((table, Some(e))) => {
+            eprintln!("{}: {}; restored the backup from the last save", scores_file.display(), e);
+            (table, Some(scores_file))
         }
-    }
-
-    fn draw(&self, canvas: &mut sdl2::render::Canvas<sdl2::video::Window>) {
-        if !self.alive { return; }
-        canvas.set_draw_color(Color::WHITE);
-        let _ = canvas.fill_rect(Rect::new(self.x, self.y, self.w, self.h));
-    }
-
-    fn rect(&self) -> Rect { 
-        Rect::new(self.x, self.y, self.w, self.h) 
-    }
-}
-
-fn overlap_x(a: &Alien, b: &Alien) -> bool {
-    let ax0 = a.x;
-    let ax1 = a.x + a.w();
-    let bx0 = b.x;
-    let bx1 = b.x + b.w();
-    ax0 < bx1 && bx0 < ax1
-}
-
-fn is_bottommost(a: &Alien, aliens: &[Alien]) -> bool {
-    if !a.alive { return false; }
-    !aliens.iter().any(|other| other.alive && overlap_x(a, other) && other.y > a.y)
-}
-
-fn bottom_shooters(aliens: &[Alien]) -> Vec<usize> {
-    aliens.iter().enumerate()
-        .filter(|(_, a)| is_bottommost(a, aliens))
-        .map(|(i, _)| i)
-        .collect()
-}
-
-fn drawing(canvas: &mut sdl2::render::Canvas<sdl2::video::Window>, design: &Vec<Vec<i32>>, x: i32, y: i32) {
-    canvas.set_draw_color(Color::WHITE);
-
-    for (row_idx, row) in design.iter().enumerate() {
-        for (col_idx, &pixel) in row.iter().enumerate() {
-            if pixel == 1 {
-                let rect = Rect::new(
-                    x + (col_idx as i32 * PIXEL as i32),
-                    y + (row_idx as i32 * PIXEL as i32),
-                    PIXEL,
-                    PIXEL,
-                );
-                let _ = canvas.fill_rect(rect);
-            }
+        Err(e) => {
+            eprintln!("{}: {}; high scores will not be saved this session", scores_file.display(), e);
+            (ScoreTable::default(), None)
         }
-    }
-}
-
-enum Position {
-    Center,
-    TopLeft,
-    TopRight,
-    BottomLeft,
-    BottomRight,
-}
-
-fn text_render(
-    text_string: &str,
-    position: Position,
-    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
-    texture_creator: &sdl2::render::TextureCreator<sdl2::video::WindowContext>,
-    font: &sdl2::ttf::Font
-) {
-    let title_surface = font
-        .render(text_string)
-        .blended(Color::WHITE)
-        .unwrap();
-    let title_texture = texture_creator
-        .create_texture_from_surface(&title_surface)
-        .unwrap();
-    let TextureQuery { width: title_w, height: title_h, .. } = title_texture.query();
-    let target = match position {
-        Position::Center => sdl2::rect::Rect::new(
-            (WINDOW_W - title_w as i32) / 2,
-            (WINDOW_H - title_h as i32) / 2,
-            title_w,
-            title_h,
-        ),
-        Position::TopLeft => sdl2::rect::Rect::new(10, 15, title_w, title_h), 
-        Position::TopRight => sdl2::rect::Rect::new(WINDOW_W - 10 - title_w as i32, 15, title_w, title_h),
-        Position::BottomLeft => sdl2::rect::Rect::new(10, WINDOW_H * 4 / 5, title_w, title_h),
-        Position::BottomRight => sdl2::rect::Rect::new(WINDOW_W - 10 - title_w as i32, WINDOW_H * 4 / 5, title_w, title_h),
     };
+    if first_run && scores.import_legacy(Path::new(scores::LEGACY_FILE)) {
+        save_scores(&scores, scores_path.as_deref());
+    }
 
-    canvas.copy(&title_texture, None, Some(target)).unwrap();
-}
-
-fn load_highscore() -> i32 {
-    fs::read_to_string(HIGHSCORE_PATH)
-        .ok()
-        .and_then(|s| s.trim().parse::<i32>().ok())
-        .unwrap_or(0)
-}
-
-fn save_highscore(score: i32) {
-    let _ = fs::write(HIGHSCORE_PATH, score.to_string());
-}
+    let sprites = SpriteBank::load_dir(Path::new(sprites::SPRITES_DIR)).unwrap_or_else(|e| exit_with(1, e));
+    let heart = sprites.require("heart").unwrap_or_else(|e| exit_with(1, e)).clone();
+    let waves = waves::load(Path::new(waves::WAVES_PATH), &sprites)
+        .unwrap_or_else(|e| exit_with(1, format!("{}: {}", waves::WAVES_PATH, e)));
+    let data_hash = replay::data_hash(Path::new(sprites::SPRITES_DIR), Path::new(waves::WAVES_PATH))
+        .unwrap_or_else(|e| exit_with(1, e));
+    let replay = args.replay.as_deref()
+        .map(|path| load_replay(path, data_hash))
+        .transpose()
+        .unwrap_or_else(|e| exit_with(2, e));
+    let mut world = World::new(sprites.clone(), waves, config.seed.unwrap_or_else(rng::seed_from_time), config.loadout())
+        .unwrap_or_else(|e| exit_with(1, e));
+
+    let sdl_context = sdl2::init().unwrap_or_else(|e| exit_with(1, e));
+    let video_subsystem = sdl_context.video().unwrap_or_else(|e| exit_with(1, e));
+
+    let mut canvas = display::create_canvas(&video_subsystem, config.scale, config.fullscreen)
+        .unwrap_or_else(|e| exit_with(1, e));
+    let mut event_pump = sdl_context.event_pump().unwrap_or_else(|e| exit_with(1, e));
+    let mut audio = Audio::new(&sdl_context, config.volume);
+    let mut gamepads = Gamepads::new(&sdl_context);
 
-fn game_reset(
-    player: &mut Player,
-    aliens: &mut Vec<Alien>,
-    mothership: &mut Alien,
-    mothership_sprite: &Vec<Vec<i32>>,
-    last_trip: &mut Instant,
-    player_bullet: &mut Vec<Bullet>,
-    enemy_bullet: &mut Vec<Bullet>,
-    enemy_fire_timer: &mut Instant,
-    enemy_fire_interval: &mut Duration,
-    direction: &mut i32,
-    shields: &mut Vec<Shield>,
-    shield_sprite: &Vec<Vec<i32>>,
-    shield_w: i32,
-    alien_1_a: &Vec<Vec<i32>>,
-    alien_1_b: &Vec<Vec<i32>>,
-    alien_2_a: &Vec<Vec<i32>>,
-    alien_2_b: &Vec<Vec<i32>>,
-    wave_level: &mut u32,
-) {
-    *wave_level = 1;
-    *aliens = wave(alien_1_a, alien_1_b, alien_2_a, alien_2_b, *wave_level);
-
-    *mothership = Alien::new(Rc::new(vec![mothership_sprite.clone()]), -100, 20);
-    *last_trip = Instant::now();
-
-    player_bullet.clear();
-    enemy_bullet.clear();
-
-    *enemy_fire_timer = Instant::now();
-    *enemy_fire_interval = Duration::from_millis(900);
-
-    *direction = 1;
-
-    player.x = WINDOW_W / 2;
-    player.lives = 3;
-    
-    shields.clear();
-    let shield_y = WINDOW_H - 150;
-    let shield_hp = 6;
-    let shield_gap = WINDOW_W / 4;
-    shields.push(Shield::new(shield_sprite.clone(), shield_gap - shield_w / 2, shield_y, shield_hp));
-    shields.push(Shield::new(shield_sprite.clone(), 2 * shield_gap - shield_w / 2, shield_y, shield_hp));
-    shields.push(Shield::new(shield_sprite.clone(), 3 * shield_gap - shield_w / 2, shield_y, shield_hp));
-}
+    let texture_creator = canvas.texture_creator();
+    let mut textures = SpriteTextures::new(&texture_creator, &sprites).unwrap_or_else(|e| exit_with(1, e));
+    let mut effects = EffectPool::new(&sprites, config.effects).unwrap_or_else(|e| exit_with(1, e));
 
-pub fn main() {
-    let mut high_score: i32 = load_highscore();
-    let mut wave_level: u32 = 1;
-
-    let spaceship = vec![
-        vec![0, 0, 1, 0, 0],
-        vec![0, 1, 1, 1, 0],
-        vec![1, 1, 1, 1, 1],
-        vec![1, 1, 0, 1, 1],
-        vec![1, 0, 0, 0, 1],
-    ];
-    
-    let mut player = Player::new(spaceship.clone(), WINDOW_W / 2, WINDOW_H - 50, 3);
-    let spaceship_width = (spaceship[0].len() as i32) * PIXEL as i32;
-    // let spaceship_y: i32 = WINDOW_H -50;
-    // let mut spaceship_x: i32 = WINDOW_W / 2;
- 
-    let hearts = vec![
-        vec![0, 1, 0, 1, 0],
-        vec![1, 1, 1, 1, 1],
-        vec![1, 1, 1, 1, 1],
-        vec![0, 1, 1, 1, 0],
-        vec![0, 0, 1, 0 ,0],
-    ];
-
-    let h_w = (hearts[0].len() as i32) * PIXEL as i32;
-    let h_h = (hearts.len() as i32) * PIXEL as i32;
-
-    let shield = vec![
-        vec![0,0,1,1,1,1,1,1,1,1,0,0],
-        vec![0,1,1,1,1,1,1,1,1,1,1,0],
-        vec![1,1,1,1,1,1,1,1,1,1,1,1],
-        vec![1,1,1,1,1,1,1,1,1,1,1,1],
-        vec![1,1,1,1,1,1,1,1,1,1,1,1],
-        vec![1,1,1,1,0,0,0,0,1,1,1,1],
-        vec![1,1,1,0,0,0,0,0,0,1,1,1],
-    ];
-
-    let mut shields: Vec<Shield> = Vec::new();
-    let shield_w = (shield[0].len() as i32) * PIXEL as i32;
-
-    let alien_1_a = vec![
-        vec![1, 1, 0, 0, 0, 1, 1],
-        vec![0, 1, 1, 1, 1, 1, 0],
-        vec![0, 1, 0, 0, 0, 1, 0],
-        vec![1, 0, 0, 0, 0, 0, 1],
-        vec![1, 0, 0, 0, 0, 0, 1],
-        vec![0, 1, 0, 0, 0, 1, 0],
-    ];
-
-    let alien_1_b = vec![
-        vec![0, 1, 0, 0, 0, 1, 0],
-        vec![1, 1, 1, 1, 1, 1, 1],
-        vec![1, 1, 0, 0, 0, 1, 1],
-        vec![0, 1, 1, 0, 1, 1, 0],
-        vec![0, 1, 0, 1, 0, 1, 0],
-        vec![1, 0, 0, 1, 0, 0, 1],
-    ];
-
-    let alien_2_a = vec![
-        vec![1, 1, 0, 1, 0, 1, 1],
-        vec![1, 1, 1, 1, 1, 1, 1],
-        vec![1, 1, 1, 0, 1, 1, 1],
-        vec![0, 1, 0, 0, 0, 1, 0],
-        vec![0, 1, 0, 0, 0, 1, 0],
-        vec![0, 0, 1, 0, 1, 0, 0],
-    ];
-
-    let alien_2_b = vec![
-        vec![1, 1, 0, 1, 0, 1, 1],
-        vec![1, 1, 1, 1, 1, 1, 1],
-        vec![1, 0, 1, 1, 1, 0, 1],
-        vec![0, 1, 0, 1, 0, 1, 0],
-        vec![0, 1, 0, 0, 0, 1, 0],
-        vec![0, 1, 1, 0, 1, 1, 0],
-    ];
-
-    let mothership_sprite = vec![
-        vec![0, 0, 0, 1, 1, 1, 1, 1, 1, 0, 0, 0],
-        vec![0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0],
-        vec![1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
-        vec![1, 1, 0, 1, 0, 1, 1, 0, 1, 0, 1, 1],
-        vec![1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
-        vec![0, 1, 1, 1, 0, 1, 1, 0, 1, 1, 1, 0],
-        vec![0, 0, 1, 0, 0, 0, 0, 0, 0, 1, 0, 0],
-    ];
-
-    let mut mothership_cd = Duration::from_millis(5000);
-    let mut last_trip = Instant::now();
-    let mut mothership = Alien::new(Rc::new(vec![mothership_sprite.clone()]), -100, 20); 
-
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-
-    let window = video_subsystem
-        .window("space-invade-rs", WINDOW_W as u32, WINDOW_H as u32)
-        .position_centered()
-        .build()
-        .unwrap();
-
-    let mut canvas = window.into_canvas().build().unwrap();
-    let mut event_pump = sdl_context.event_pump().unwrap();
-
-    let mut aliens = wave(&alien_1_a, &alien_1_b, &alien_2_a, &alien_2_b, wave_level);
-
-    let mut direction: i32 = 1;
-    let mut step_timer = Instant::now();
-    let mut step_interval = Duration::from_millis(1200);
-    let step = PIXEL as i32;
-    let drop = PIXEL as i32 * 2;
-
-    let mut player_bullet: Vec<Bullet> = Vec::new();
-
-    let mut enemy_bullet: Vec<Bullet> = Vec::new();
-    let mut enemy_fire_timer = Instant::now();
-    let mut enemy_fire_interval = Duration::from_millis(900);
+    if args.bench_render {
+        bench::run(&mut canvas, &mut textures, sprites).unwrap_or_else(|e| exit_with(1, e));
+        return;
+    }
 
-    let texture_creator = canvas.texture_creator();
-    let ttf_context = sdl2::ttf::init().unwrap();
-    let font_big = ttf_context.load_font("assets/PressStart2P-Regular.ttf", 32).unwrap();
-    let font_small = ttf_context.load_font("assets/PressStart2P-Regular.ttf", 16).unwrap();
+    let ttf_context = sdl2::ttf::init().unwrap_or_else(|e| exit_with(1, e));
+    let font = |size| ttf_context.load_font(&config.font, size)
+        .unwrap_or_else(|e| exit_with(1, format!("{}: {}", config.font.display(), e)));
+    let (font_big, font_small) = (font(32), font(16));
+    let mut text = TextCache::new(&texture_creator, font_big, font_small);
 
-    let mut i = 0;
     let mut state = GameState::TitleScreen;
-
-    let mut score: i32 = 0;
+    let mut options_menu = OptionsMenu::default();
+    let mut controls_menu = ControlsMenu::default();
+    let mut initials = InitialsEntry::default();
+    let mut loss_reason = LossReason::OutOfLives;
+    let mut intro_ticks = 0;
+    let mut clock = FixedClock::new();
+
+    let mut recording = Replay::new(world.seed(), config.loadout(), data_hash);
+
+    let mut replay_tick = 0;
+    let mut replay_paused = false;
+    if let Some(replay) = &replay {
+        world.reset(replay.seed, replay.loadout);
+        state = GameState::Replay;
+    }
 
     'running: loop {
-        i = (i + 1) % 255;
         canvas.set_draw_color(Color::RGB(0, 0, 0));
         canvas.clear();
 
         for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit {..} |
-                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
-                    break 'running
+            let (key, action, from_pad) = match event {
+                Event::Quit {..} => break 'running,
+                Event::Window { win_event: WindowEvent::SizeChanged(..), .. } => {
+                    display::fit(&mut canvas);
+                    continue;
+                }
+                Event::KeyDown { scancode: Some(Scancode::F11), repeat: false, .. } => {
+                    display::toggle_fullscreen(&mut canvas);
+                    continue;
+                }
+                Event::KeyDown { scancode: Some(Scancode::Return), keymod, repeat: false, .. }
+                    if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) =>
+                {
+                    display::toggle_fullscreen(&mut canvas);
+                    continue;
+                }
+                Event::KeyDown { scancode: Some(key), repeat: false, ..} => (Some(key), config.controls.action(key), false),
+                _ => match gamepads.handle(&event) {
+                    Some((key, action)) => (key, action, true),
+                    None => continue,
                 },
-                Event::KeyDown { keycode: Some(Keycode::Return), ..} => match state {
-                    GameState::TitleScreen => state = GameState::Playing,
+            };
+
+            if let GameState::Controls = state {
+                let outcome = match key {
+                    Some(key) if !from_pad => controls_menu.key_down(key, &mut config.controls),
+                    _ => controls_menu.pad_press(key, action, &mut config.controls),
+                };
+                match outcome {
+                    MenuOutcome::Stay => {}
+                    MenuOutcome::Changed => save_config(&config),
+                    MenuOutcome::Leave => state = GameState::Options,
+                }
+                continue;
+            }
+
+            if let GameState::Options = state {
+                match options_menu.key_down(key, action, &mut config) {
+                    OptionsOutcome::Stay => {}
+                    OptionsOutcome::Changed => {
+                        audio.set_volume(config.volume);
+                        effects.set_level(config.effects);
+                        save_config(&config);
+                    }
+                    OptionsOutcome::Controls => {
+                        controls_menu = ControlsMenu::default();
+                        state = GameState::Controls;
+                    }
+                    OptionsOutcome::Leave => state = GameState::TitleScreen,
+                }
+                continue;
+            }
+
+            if let GameState::EnterInitials = state {
+                if action == Some(Action::Back) {
+                    state = GameState::TitleScreen;
+                } else if let Some(name) = initials.key_down(key, action) {
+                    scores.name(name);
+                    save_scores(&scores, scores_path.as_deref());
+                    state = GameState::TitleScreen;
+                }
+                continue;
+            }
+
+            // Pad `A` fires as well as confirms, so a press meant as a shot
+            // must not throw away a paused run or skip the loss screen. There
+            // the pad uses Start to go on and Back to leave a paused game.
+            let action = match (action, &state) {
+                (Some(Action::Confirm), GameState::Pause | GameState::Lost) if from_pad => None,
+                (Some(Action::Pause), GameState::Lost) if from_pad => Some(Action::Confirm),
+                (Some(Action::Back), GameState::Pause) if from_pad => Some(Action::Confirm),
+                _ => action,
+            };
+
+            match (action, key) {
+                (Some(Action::Back), _) => break 'running,
+                (Some(Action::Confirm), _) => match state {
+                    GameState::TitleScreen => {
+                        world.reset(config.seed.unwrap_or_else(rng::seed_from_time), config.loadout());
+                        effects.clear();
+                        recording = Replay::new(world.seed(), config.loadout(), data_hash);
+                        scores.end_game();
+                        audio.stop();
+                        clock.reset();
+                        intro_ticks = WAVE_INTRO_TICKS;
+                        state = GameState::WaveIntro;
+                    }
+                    GameState::WaveIntro => {},
                     GameState::Playing => {},
                     GameState::Pause => state = GameState::TitleScreen,
+                    GameState::Lost if scores.has_unnamed() => {
+                        initials = InitialsEntry::default();
+                        state = GameState::EnterInitials;
+                    }
                     GameState::Lost => state = GameState::TitleScreen,
+                    GameState::EnterInitials => {},
+                    GameState::Options => {},
+                    GameState::Controls => {},
+                    GameState::Replay => {
+                        if let Some(replay) = &replay {
+                            world.reset(replay.seed, replay.loadout);
+                            effects.clear();
+                            replay_tick = 0;
+                            audio.stop();
+                            clock.reset();
+                        }
+                    }
+                }
+                (Some(Action::Pause | Action::Fire), _) if matches!(state, GameState::Replay) => {
+                    replay_paused = !replay_paused;
+                    clock.reset();
                 }
-                Event::KeyDown { keycode: Some(Keycode::P), ..} => match state {
-                    GameState::Playing => state = GameState::Pause,
-                    GameState::Pause => state = GameState::Playing,
+                (Some(Action::Pause), _) => match state {
+                    GameState::Playing => {
+                        state = GameState::Pause;
+                    }
+                    GameState::Pause => {
+                        clock.reset();
+                        state = GameState::Playing;
+                    }
                     GameState::TitleScreen => {},
+                    GameState::WaveIntro => {},
                     GameState::Lost => {},
+                    GameState::EnterInitials => {},
+                    GameState::Options => {},
+                    GameState::Controls => {},
+                    GameState::Replay => {},
+                }
+                (None, Some(Scancode::O)) if matches!(state, GameState::TitleScreen) => {
+                    options_menu = OptionsMenu::default();
+                    state = GameState::Options;
+                }
+                (None, Some(Scancode::M)) => {
+                    audio.toggle_mute();
+                    config.volume = audio.volume();
+                    save_config(&config);
+                }
+                (None, Some(Scancode::Minus | Scancode::Equals)) => {
+                    let mut volume = audio.volume();
+                    volume.master = if key == Some(Scancode::Minus) {
+                        volume.master.saturating_sub(options::VOLUME_STEP)
+                    } else {
+                        (volume.master + options::VOLUME_STEP).min(100)
+                    };
+                    audio.set_volume(volume);
+                    config.volume = volume;
+                    save_config(&config);
                 }
 
                 _ => {}
@@ -552,243 +417,148 @@ pub fn main() {
 
         match state {
             GameState::TitleScreen => {
+                scores.draw(&mut canvas, &mut text, -260);
                 let press_enter: &str = "Press Enter";
-                text_render(press_enter, Position::Center, &mut canvas, &texture_creator, &font_big);
-                let high_text = format!("High Score: {}", high_score);
-                text_render(&high_text, Position::BottomLeft, &mut canvas, &texture_creator, &font_small);
-                score = 0;
-                game_reset(
-                    &mut player,
-                    &mut aliens,
-                    &mut mothership,
-                    &mothership_sprite,
-                    &mut last_trip,
-                    &mut player_bullet,
-                    &mut enemy_bullet,
-                    &mut enemy_fire_timer,
-                    &mut enemy_fire_interval,
-                    &mut direction,
-                    &mut shields,
-                    &shield,
-                    shield_w,
-                    &alien_1_a, &alien_1_b, &alien_2_a, &alien_2_b,
-                    &mut wave_level,
-                ); 
+                text.draw(&mut canvas, FontSize::Big, press_enter, Position::BelowCenter(60));
+                let high_text = match scores_path {
+                    Some(_) => format!("High Score: {}", scores.best()),
+                    None => "Score file unreadable".to_string(),
+                };
+                text.draw(&mut canvas, FontSize::Small, &high_text, Position::BottomLeft);
+                text.draw(&mut canvas, FontSize::Small, "O: options", Position::BottomRight);
             }
 
-            GameState::Playing => {
-                let total_aliens = aliens.len();
-
-                drawing(&mut canvas, &player.sprite, player.x, player.y);
-
-                let score_text = format!("Score: {}", score);
-                text_render(&score_text, Position::TopLeft, &mut canvas, &texture_creator, &font_small);
-                // let exit_text: &str = "Escape to exit";
-                // text_render(exit_text, Position::TopRight, &mut canvas, &texture_creator, &font_small);
-
-                let mut h_x = WINDOW_W - h_w - 10;
-                let h_y = 15;
-                for _ in 0..player.lives {
-                    drawing(&mut canvas, &hearts, h_x, h_y);
-                    h_x -= h_w + 5;
-                }
-
-                for s in &shields {
-                    s.draw(&mut canvas);
-                }
-
-                mothership.draw(&mut canvas);
-
-                for a in aliens.iter_mut() {
-                    a.update_animation();
-                }
-
-                for alien in &aliens {
-                    alien.draw(&mut canvas);
-                }
-
-                let key_state  = event_pump.keyboard_state();
-
-                if key_state.is_scancode_pressed(Scancode::A) {            
-                    player.x -= 5;
-                    player.x = player.x.max(0);
-                }
-
-                if key_state.is_scancode_pressed(Scancode::D) {
-                    player.x += 5;
-                    player.x = player.x.min(WINDOW_W - spaceship_width);
-                }
-
-                if key_state.is_scancode_pressed(Scancode::Space) {
-                    if player_bullet.is_empty() {
-                        let tip_x = player.x + (spaceship_width / 2) - (PIXEL as i32 / 2);
-                        let tip_y = player.y - PIXEL as i32 * 2;
-                        player_bullet.push(Bullet::new(tip_x, tip_y, -6));
-                    }
-                }
-
-                if last_trip.elapsed() >= mothership_cd {
-                    if mothership.alive && mothership.x <= WINDOW_W + mothership.w() {
-                        mothership.translate(5, 0);
-                    } else { 
-                        if mothership.alive {
-                            last_trip = Instant::now();
-                            mothership.x = -100;
-                        } else {
-                            mothership_cd = Duration::from_millis(10000);
-                            last_trip = Instant::now();
-                            mothership.x = -100;
-                            mothership.alive = true;
-                        }
-                    }
-                }        
+            GameState::EnterInitials => initials.draw(&mut canvas, &mut text),
 
-                if step_timer.elapsed() >= step_interval {
-                    let mut descend = false;
+            GameState::Options => options_menu.draw(&mut canvas, &mut text, &config),
 
-                    if let Some((min_x, max_x, _max_y)) = fleet_manager(&aliens) {
-                        let left_limit = 10;
-                        let right_limit = WINDOW_W - 10;
+            GameState::Controls => controls_menu.draw(&mut canvas, &mut text, &config.controls),
 
-                        if direction > 0 && max_x + step >= right_limit {
-                            direction = -1;
-                            descend = true;
-                        }
-                        if direction < 0 && min_x - step <= left_limit {
-                            direction = 1;
-                            descend = true;
-                        }
-                    }
-
-                    let dx = direction * step;
-                    let dy = if descend { drop } else { 0 };
-
-                    for a in aliens.iter_mut().filter(|a| a.alive) {
-                        a.translate(if descend { 0 } else { dx }, dy);
-                    }
-
-                    step_timer = Instant::now();
-                    let alive_aliens = aliens.iter().filter(|a| a.alive).count().max(1);
-                    let ratio = alive_aliens as f32 / total_aliens as f32; // 1.0 .. 0.0
-                    step_interval = Duration::from_millis((200.0 + 400.0 * ratio) as u64);
-                }
-
-                for b in player_bullet.iter_mut() {
-                    b.update();
-
-                    if b.alive {
-                        for s in shields.iter_mut() {
-                            if s.alive && b.rect().has_intersection(s.rect()) {
-                                s.hp -= 1;
-                                if s.hp <= 0 { s.alive = false; }
-                                b.alive = false;
-                                break;
-                            }
-                        }
-                        if mothership.alive && b.rect().has_intersection(mothership.rect()) {
-                            mothership.alive = false;
-                            b.alive = false;
-                            score += 175;
-                            if score > high_score {
-                                high_score = score;
-                                save_highscore(high_score);
-                            }
-                        }
-
-                        // if b.alive { 
-                        for a in &mut aliens {
-                            if !a.alive { continue; }
-                            if b.rect().has_intersection(a.rect()) {
-                                a.alive = false;
-                                b.alive = false;
-                                score += 20;
-                                if score > high_score {
-                                    high_score = score;
-                                    save_highscore(high_score);
-                                } 
-                                break;
-                            }
-                        }
-                    }
-
-                    b.draw(&mut canvas);
+            GameState::WaveIntro => {
+                intro_ticks = intro_ticks.saturating_sub(clock.advance() as u64);
+                if intro_ticks == 0 {
+                    state = GameState::Playing;
                 }
 
-                player_bullet.retain(|b| b.alive);
-
-                if enemy_fire_timer.elapsed() >= enemy_fire_interval {
-                    let shooters = bottom_shooters(&aliens);
-                    if !shooters.is_empty() {
-                        let idx = shooters[(i as usize) % shooters.len()];
-                        let a = &aliens[idx];
-                        let bx = a.x + (a.w() / 2) - (PIXEL as i32 / 2);
-                        let by = a.y + a.h();
-                        enemy_bullet.push(Bullet::new(bx, by, 5));
-                    }
-                    enemy_fire_timer = Instant::now();
-                }
+                render::draw_world(&mut canvas, &mut textures, &world);
+                effects.draw(&mut canvas, &textures, &mut text);
+                draw_hud(&mut canvas, &mut text, &textures, &world, &heart);
+
+                let stats = &world.stats;
+                let wave_text = format!("Wave {}", world.wave_level);
+                let pace_text = format!("Fleet speed x{:.2}  Fire rate x{:.2}", 1.0 / stats.step_factor, 1.0 / stats.fire_factor);
+                let shots_text = format!("Shot speed {}  Max shots {}", stats.bullet_speed, stats.max_enemy_shots);
+                text.draw(&mut canvas, FontSize::Big, &wave_text, Position::Center);
+                text.draw(&mut canvas, FontSize::Small, &pace_text, Position::BelowCenter(40));
+                text.draw(&mut canvas, FontSize::Small, &shots_text, Position::BelowCenter(65));
+            }
 
-                for eb in enemy_bullet.iter_mut() {
-                    eb.update();
-
-                    if eb.alive {
-                        for s in shields.iter_mut() {
-                            if s.alive && eb.rect().has_intersection(s.rect()) {
-                                s.hp -= 1;
-                                if s.hp <= 0 { s.alive = false; }
-                                eb.alive = false;
-                                break;
-                            }
-                        }
+            GameState::Playing => {
+                let keys = event_pump.keyboard_state();
+                let input = controls::input(|action| config.controls.held(&keys, action) || gamepads.held(action));
+
+                for _ in 0..clock.advance() {
+                    recording.record(input);
+                    let events = world.step(input);
+                    audio.play_events(&events);
+                    effects.step(&events);
+                    let game_over = events.iter().find_map(|e| match e {
+                        GameEvent::GameOver(reason) => Some(*reason),
+                        _ => None,
+                    });
+                    if let Some(reason) = game_over {
+                        save_replay(&recording, &replay_dir);
+                        scores.record(world.score, world.wave_level);
+                        save_scores(&scores, scores_path.as_deref());
+                        loss_reason = reason;
+                        state = GameState::Lost;
+                        break;
                     }
-
-                    if eb.rect().has_intersection(player.rect()) {
-                        player.lives -= 1;
-                        eb.alive = false;
-                        if player.lives < 1 {
-                            state = GameState::Lost;
+                    if events.contains(&GameEvent::WaveCleared) {
+                        scores.record(world.score, world.wave_level);
+                        if scores.has_unnamed() {
+                            save_scores(&scores, scores_path.as_deref());
                         }
+                        intro_ticks = WAVE_INTRO_TICKS;
+                        state = GameState::WaveIntro;
                         break;
                     }
-
-                    eb.draw(&mut canvas);
-                }
-                enemy_bullet.retain(|b| b.alive);
-                shields.retain(|s| s.alive);
-
-                if aliens.iter().all(|a| !a.alive) {
-                    wave_level += 1;
-                    aliens = wave(&alien_1_a, &alien_1_b, &alien_2_a, &alien_2_b, wave_level);
-                    direction = 1;
-                    step_timer = Instant::now();
-                    step_interval = Duration::from_millis(1200);
                 }
+
+                render::draw_world(&mut canvas, &mut textures, &world);
+                effects.draw(&mut canvas, &textures, &mut text);
+                draw_hud(&mut canvas, &mut text, &textures, &world, &heart);
             }
 
             GameState::Pause => {
                 let game_paused: &str = "Game Paused";
                 let p_continue: &str = "P to continue";
                 let enter_title: &str = "Enter to go to title";
-                text_render(game_paused, Position::Center, &mut canvas, &texture_creator, &font_big);
-                text_render(p_continue, Position::BottomLeft, &mut canvas, &texture_creator, &font_small);
-                text_render(enter_title, Position::BottomRight, &mut canvas, &texture_creator, &font_small);
+                text.draw(&mut canvas, FontSize::Big, game_paused, Position::Center);
+                text.draw(&mut canvas, FontSize::Small, p_continue, Position::BottomLeft);
+                text.draw(&mut canvas, FontSize::Small, enter_title, Position::BottomRight);
             }
 
             GameState::Lost => {
-                if score > high_score {
-                    high_score = score;
-                    save_highscore(high_score);
-                }
-                let game_lost: &str = "You Lost!";
-                let enter_title: &str = "Enter to go to title";
+                let game_lost: &str = match loss_reason {
+                    LossReason::OutOfLives => "You Lost!",
+                    LossReason::Invaded => "Invaded!",
+                };
+                let enter_title: &str = if scores.has_unnamed() { "Enter to continue" } else { "Enter to go to title" };
                 let exit_text: &str = "Esc to exit";
-                text_render(game_lost, Position::Center, &mut canvas, &texture_creator, &font_big);
-                text_render(enter_title, Position::BottomLeft, &mut canvas, &texture_creator, &font_small);
-                text_render(exit_text, Position::BottomRight, &mut canvas, &texture_creator, &font_small);
+                text.draw(&mut canvas, FontSize::Big, game_lost, Position::Center);
+                text.draw(&mut canvas, FontSize::Small, enter_title, Position::BottomLeft);
+                text.draw(&mut canvas, FontSize::Small, exit_text, Position::BottomRight);
+            }
+
+            GameState::Replay => {
+                let Some(replay) = &replay else { break 'running };
+
+                let fast_forward = event_pump.keyboard_state().is_scancode_pressed(Scancode::F);
+                let ticks = clock.advance() * if fast_forward { REPLAY_FAST_FORWARD } else { 1 };
+                if !replay_paused {
+                    for _ in 0..ticks {
+                        let Some(input) = replay.input_at(replay_tick) else { break };
+                        let events = world.step(input);
+                        audio.play_events(&events);
+                        effects.step(&events);
+                        replay_tick += 1;
+                    }
+                }
+
+                render::draw_world(&mut canvas, &mut textures, &world);
+                effects.draw(&mut canvas, &textures, &mut text);
+                draw_hud(&mut canvas, &mut text, &textures, &world, &heart);
+
+                let status = if replay_tick >= replay.len() {
+                    "Replay over - Enter to restart".to_string()
+                } else if replay_paused {
+                    "Paused - P to resume".to_string()
+                } else if fast_forward {
+                    format!("Replay x{}", REPLAY_FAST_FORWARD)
+                } else {
+                    "Replay - F fast, P pause".to_string()
+                };
+                text.draw(&mut canvas, FontSize::Small, &status, Position::BottomLeft);
             }
         }
 
+        let flying = match state {
+            GameState::Playing => true,
+            GameState::Replay => !replay_paused,
+            _ => false,
+        };
+        audio.mothership(flying && world.mothership.alive);
+
         canvas.present();
+        text.end_frame();
         ::std::thread::sleep(Duration::from_millis(1000 / 60));
     }
+
+    if matches!(state, GameState::WaveIntro | GameState::Playing | GameState::Pause) {
+        scores.record(world.score, world.wave_level);
+        if scores.has_unnamed() {
+            save_scores(&scores, scores_path.as_deref());
+        }
+    }
 }
@@ -0,0 +1,118 @@
+use sdl2::{
+    keyboard::Scancode,
+    pixels::Color,
+    render::Canvas,
+    video::Window,
+};
+
+use crate::{
+    config::Config,
+    controls::Action,
+    effects::EffectsLevel,
+    text::{
+        FontSize,
+        Position,
+        TextCache,
+    },
+    world::FireMode,
+};
+
+pub const VOLUME_STEP: u8 = 10;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Row {
+    FireMode,
+    Master,
+    Sfx,
+    Music,
+    Mute,
+    Effects,
+    Controls,
+}
+
+const ROWS: [Row; 7] = [Row::FireMode, Row::Master, Row::Sfx, Row::Music, Row::Mute, Row::Effects, Row::Controls];
+
+pub enum OptionsOutcome {
+    Stay,
+    /// A setting changed and should be applied and saved.
+    Changed,
+    /// Open the "Controls" screen.
+    Controls,
+    Leave,
+}
+
+/// The "Options" screen. Up and down pick a row, left and right change it.
+#[derive(Default)]
+pub struct OptionsMenu {
+    selected: usize,
+}
+
+impl OptionsMenu {
+    pub fn key_down(&mut self, key: Option<Scancode>, action: Option<Action>, config: &mut Config) -> OptionsOutcome {
+        let row = ROWS[self.selected];
+        let step = match (key, action) {
+            (Some(Scancode::Up), _) => {
+                self.selected = (self.selected + ROWS.len() - 1) % ROWS.len();
+                return OptionsOutcome::Stay;
+            }
+            (Some(Scancode::Down), _) => {
+                self.selected = (self.selected + 1) % ROWS.len();
+                return OptionsOutcome::Stay;
+            }
+            (_, Some(Action::Back)) => return OptionsOutcome::Leave,
+            (_, Some(Action::Confirm)) if row == Row::Controls => return OptionsOutcome::Controls,
+            (Some(Scancode::Left), _) | (_, Some(Action::MoveLeft)) => -1,
+            (Some(Scancode::Right), _) | (_, Some(Action::MoveRight)) | (_, Some(Action::Confirm)) => 1,
+            _ => return OptionsOutcome::Stay,
+        };
+
+        let volume = &mut config.volume;
+        match row {
+            Row::FireMode => config.fire_mode = cycle(&FireMode::ALL, config.fire_mode, step),
+            Row::Master => volume.master = adjust(volume.master, step),
+            Row::Sfx => volume.sfx = adjust(volume.sfx, step),
+            Row::Music => volume.music = adjust(volume.music, step),
+            Row::Mute => volume.muted = !volume.muted,
+            Row::Effects => config.effects = cycle(&EffectsLevel::ALL, config.effects, step),
+            Row::Controls => return OptionsOutcome::Stay,
+        }
+        OptionsOutcome::Changed
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, text: &mut TextCache, config: &Config) {
+        text.draw(canvas, FontSize::Big, "Options", Position::BelowCenter(-220));
+
+        let highlight = Color::RGB(255, 220, 60);
+        let volume = &config.volume;
+        for (i, row) in ROWS.iter().enumerate() {
+            let line = match row {
+                Row::FireMode => format!("{:<15} < {} >", "Fire mode", config.fire_mode.name()),
+                Row::Master => format!("{:<15} < {}% >", "Master volume", volume.master),
+                Row::Sfx => format!("{:<15} < {}% >", "Sound effects", volume.sfx),
+                Row::Music => format!("{:<15} < {}% >", "Music", volume.music),
+                Row::Mute => format!("{:<15} < {} >", "Mute", if volume.muted { "on" } else { "off" }),
+                Row::Effects => format!("{:<15} < {} >", "Visual effects", config.effects.name()),
+                Row::Controls => "Controls".to_string(),
+            };
+            let color = if i == self.selected { highlight } else { Color::WHITE };
+            text.draw_colored(canvas, FontSize::Small, &line, color, Position::BelowCenter(-140 + i as i32 * 35));
+        }
+
+        if ROWS[self.selected] == Row::FireMode {
+            let w = config.weapon();
+            let info = format!("{} shot(s), {} px/tick", w.max_bullets, w.bullet_speed);
+            text.draw(canvas, FontSize::Small, &info, Position::BelowCenter(140));
+        }
+        text.draw(canvas, FontSize::Small, "Arrows change, Esc back", Position::BottomLeft);
+    }
+}
+
+/// The entry `step` places after `current` in `all`, wrapping around.
+fn cycle<T: Copy + PartialEq>(all: &[T], current: T, step: i32) -> T {
+    let i = all.iter().position(|&v| v == current).unwrap_or(0);
+    all[(i as i32 + step).rem_euclid(all.len() as i32) as usize]
+}
+
+fn adjust(level: u8, step: i32) -> u8 {
+    (level as i32 + step * VOLUME_STEP as i32).clamp(0, 100) as u8
+}
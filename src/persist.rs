@@ -0,0 +1,65 @@
+use std::{
+    ffi::OsString,
+    fs,
+    io::{
+        self,
+        Write,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+/// `path` with `suffix` appended to the file name, e.g. `scores.toml.bak`.
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Replaces `path` so that a crash at any point leaves either the old or the
+/// new contents, never a mix. The previous file is kept as `<path>.bak`.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let tmp = with_suffix(path, ".tmp");
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    if path.exists() {
+        fs::copy(path, with_suffix(path, ".bak"))?;
+    }
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_atomic_rotates_the_old_file() {
+        let dir = std::env::temp_dir().join(format!("persist-test-{}", std::process::id()));
+        let path = dir.join("nested").join("data.txt");
+
+        write_atomic(&path, b"one").unwrap();
+        assert!(!with_suffix(&path, ".bak").exists());
+        write_atomic(&path, b"two").unwrap();
+        let (current, backup) = (fs::read(&path).unwrap(), fs::read(with_suffix(&path, ".bak")).unwrap());
+        let tmp_left = with_suffix(&path, ".tmp").exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(current, b"two");
+        assert_eq!(backup, b"one");
+        assert!(!tmp_left);
+    }
+
+    #[test]
+    fn with_suffix_appends_to_the_file_name() {
+        assert_eq!(with_suffix(Path::new("a/scores.toml"), ".bak"), Path::new("a/scores.toml.bak"));
+    }
+}
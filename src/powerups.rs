@@ -0,0 +1,225 @@
+//! Capsules that destroyed enemies drop and the effects they give the player
+//! once collected. Which kinds drop, and how often, is set per wave in the
+//! waves file.
+
+use std::rc::Rc;
+
+use serde::Deserialize;
+
+use crate::{
+    clock::ticks_from_ms,
+    rng::Rng,
+    sprites::{
+        SpriteBank,
+        SpriteDef,
+        SpriteError,
+    },
+    world::{
+        Rect,
+        PIXEL,
+        WINDOW_H,
+    },
+};
+
+/// Pixels a capsule falls per tick.
+const FALL_SPEED: i32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerUpKind {
+    /// More shots on screen, fired faster.
+    RapidFire,
+    /// Every shot is three bullets in a fan.
+    TripleShot,
+    /// Shots go through aliens instead of stopping at the first.
+    PiercingLaser,
+    /// Enemy bullets bounce off the player.
+    ShieldBubble,
+    ExtraLife,
+    /// Puts every bunker back as it was at the start of the run.
+    ShieldRepair,
+}
+
+impl PowerUpKind {
+    pub const ALL: [PowerUpKind; 6] = [
+        PowerUpKind::RapidFire,
+        PowerUpKind::TripleShot,
+        PowerUpKind::PiercingLaser,
+        PowerUpKind::ShieldBubble,
+        PowerUpKind::ExtraLife,
+        PowerUpKind::ShieldRepair,
+    ];
+
+    /// Key in a drop table; the capsule sprite is `powerup_<name>`.
+    pub fn name(self) -> &'static str {
+        match self {
+            PowerUpKind::RapidFire => "rapid_fire",
+            PowerUpKind::TripleShot => "triple_shot",
+            PowerUpKind::PiercingLaser => "piercing_laser",
+            PowerUpKind::ShieldBubble => "shield_bubble",
+            PowerUpKind::ExtraLife => "extra_life",
+            PowerUpKind::ShieldRepair => "shield_repair",
+        }
+    }
+
+    /// Shown in the HUD while the effect lasts.
+    pub fn label(self) -> &'static str {
+        match self {
+            PowerUpKind::RapidFire => "Rapid fire",
+            PowerUpKind::TripleShot => "Triple shot",
+            PowerUpKind::PiercingLaser => "Laser",
+            PowerUpKind::ShieldBubble => "Bubble",
+            PowerUpKind::ExtraLife => "Extra life",
+            PowerUpKind::ShieldRepair => "Repair",
+        }
+    }
+
+    /// Ticks the effect lasts, or `None` if it happens once on pickup.
+    pub fn duration(self) -> Option<u64> {
+        match self {
+            PowerUpKind::RapidFire | PowerUpKind::TripleShot => Some(ticks_from_ms(10_000)),
+            PowerUpKind::PiercingLaser | PowerUpKind::ShieldBubble => Some(ticks_from_ms(8_000)),
+            PowerUpKind::ExtraLife | PowerUpKind::ShieldRepair => None,
+        }
+    }
+}
+
+/// Odds of a kill dropping a capsule, and of which kind, as written in the
+/// waves file. Weights are relative to each other; 0 never drops that kind.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DropTable {
+    /// Percent of alien kills that drop a capsule.
+    pub chance: u32,
+    /// Percent of mothership kills that drop a capsule.
+    pub mothership_chance: u32,
+    pub rapid_fire: u32,
+    pub triple_shot: u32,
+    pub piercing_laser: u32,
+    pub shield_bubble: u32,
+    pub extra_life: u32,
+    pub shield_repair: u32,
+}
+
+impl Default for DropTable {
+    fn default() -> Self {
+        Self {
+            chance: 5,
+            mothership_chance: 100,
+            rapid_fire: 3,
+            triple_shot: 2,
+            piercing_laser: 2,
+            shield_bubble: 2,
+            extra_life: 1,
+            shield_repair: 2,
+        }
+    }
+}
+
+impl DropTable {
+    pub fn weight(&self, kind: PowerUpKind) -> u32 {
+        match kind {
+            PowerUpKind::RapidFire => self.rapid_fire,
+            PowerUpKind::TripleShot => self.triple_shot,
+            PowerUpKind::PiercingLaser => self.piercing_laser,
+            PowerUpKind::ShieldBubble => self.shield_bubble,
+            PowerUpKind::ExtraLife => self.extra_life,
+            PowerUpKind::ShieldRepair => self.shield_repair,
+        }
+    }
+
+    pub fn total_weight(&self) -> u32 {
+        PowerUpKind::ALL.iter().map(|&k| self.weight(k)).sum()
+    }
+
+    /// What a kill drops, if anything.
+    pub fn roll(&self, rng: &mut Rng, mothership: bool) -> Option<PowerUpKind> {
+        let chance = if mothership { self.mothership_chance } else { self.chance };
+        if self.total_weight() == 0 || !rng.chance(chance as u64, 100) {
+            return None;
+        }
+        let weights = PowerUpKind::ALL.map(|k| self.weight(k) as u64);
+        rng.weighted(&weights).map(|i| PowerUpKind::ALL[i])
+    }
+}
+
+/// A falling capsule, collected by touching it.
+#[derive(Clone)]
+pub struct Capsule {
+    def: Rc<SpriteDef>,
+    pub kind: PowerUpKind,
+    pub x: i32,
+    pub y: i32,
+    pub alive: bool,
+}
+
+impl Capsule {
+    pub fn update(&mut self) {
+        self.y += FALL_SPEED;
+        if self.y >= WINDOW_H {
+            self.alive = false;
+        }
+    }
+
+    pub fn sprite(&self) -> &SpriteDef {
+        &self.def
+    }
+
+    pub fn rect(&self) -> Rect {
+        let (w, h) = self.def.cells();
+        Rect::new(self.x, self.y, (w * PIXEL as i32) as u32, (h * PIXEL as i32) as u32)
+    }
+}
+
+/// Capsule sprites for every kind, looked up once when the world is built.
+pub struct CapsuleSprites {
+    defs: Vec<Rc<SpriteDef>>,
+}
+
+impl CapsuleSprites {
+    pub fn load(sprites: &SpriteBank) -> Result<Self, SpriteError> {
+        let defs = PowerUpKind::ALL.iter()
+            .map(|k| sprites.require(&format!("powerup_{}", k.name())).cloned())
+            .collect::<Result<_, _>>()?;
+        Ok(Self { defs })
+    }
+
+    /// A capsule of `kind` centred on `center_x` with its top at `y`.
+    pub fn spawn(&self, kind: PowerUpKind, center_x: i32, y: i32) -> Capsule {
+        let ix = PowerUpKind::ALL.iter().position(|&k| k == kind).expect("every kind is in ALL");
+        let def = self.defs[ix].clone();
+        let x = center_x - def.cells().0 * PIXEL as i32 / 2;
+        Capsule { def, kind, x, y, alive: true }
+    }
+}
+
+/// Timed effects the player has, each with the tick it runs out on.
+#[derive(Clone, Debug, Default)]
+pub struct Effects {
+    until: Vec<(PowerUpKind, u64)>,
+}
+
+impl Effects {
+    pub fn active(&self, kind: PowerUpKind) -> bool {
+        self.until.iter().any(|&(k, _)| k == kind)
+    }
+
+    /// Starts `kind` at `tick`, or restarts its timer if it is running.
+    pub fn start(&mut self, kind: PowerUpKind, tick: u64, duration: u64) {
+        self.until.retain(|&(k, _)| k != kind);
+        self.until.push((kind, tick + duration));
+    }
+
+    /// Drops every effect that ran out by `tick`.
+    pub fn expire(&mut self, tick: u64) {
+        self.until.retain(|&(_, until)| until > tick);
+    }
+
+    pub fn clear(&mut self) {
+        self.until.clear();
+    }
+
+    /// Running effects in pickup order with the ticks each has left.
+    pub fn remaining(&self, tick: u64) -> impl Iterator<Item = (PowerUpKind, u64)> + '_ {
+        self.until.iter().map(move |&(k, until)| (k, until.saturating_sub(tick)))
+    }
+}
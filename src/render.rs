@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use sdl2::{
+    pixels::{
+        Color,
+        PixelFormatEnum,
+    },
+    rect::Rect,
+    render::{
+        BlendMode,
+        Canvas,
+        Texture,
+        TextureCreator,
+    },
+    surface::Surface,
+    video::{
+        Window,
+        WindowContext,
+    },
+};
+
+use crate::{
+    powerups::PowerUpKind,
+    sprites::{
+        Sprite,
+        SpriteBank,
+        SpriteDef,
+    },
+    world::{
+        Shield,
+        BUBBLE_COLOR,
+        World,
+        PIXEL,
+    },
+};
+
+/// Rasterizes a frame at one texel per cell; `PIXEL` scaling is applied by
+/// the destination rect when it is copied, with nearest-neighbour filtering.
+fn rasterize<'a>(
+    creator: &'a TextureCreator<WindowContext>,
+    sprite: &Sprite,
+    [r, g, b]: [u8; 3],
+) -> Result<Texture<'a>, String> {
+    let w = sprite.first().map(|row| row.len()).unwrap_or(0) as u32;
+    let h = sprite.len() as u32;
+    let mut surface = Surface::new(w.max(1), h.max(1), PixelFormatEnum::RGBA8888)?;
+    surface.fill_rect(None, Color::RGBA(0, 0, 0, 0))?;
+    for (y, row) in sprite.iter().enumerate() {
+        for (x, &cell) in row.iter().enumerate() {
+            if cell == 1 {
+                surface.fill_rect(Rect::new(x as i32, y as i32, 1, 1), Color::RGBA(r, g, b, 255))?;
+            }
+        }
+    }
+    let mut texture = creator.create_texture_from_surface(&surface).map_err(|e| e.to_string())?;
+    texture.set_blend_mode(BlendMode::Blend);
+    Ok(texture)
+}
+
+fn scaled(x: i32, y: i32, sprite: &Sprite) -> Rect {
+    let w = sprite.first().map(|row| row.len()).unwrap_or(0) as u32;
+    Rect::new(x, y, w * PIXEL, sprite.len() as u32 * PIXEL)
+}
+
+/// One texture per sprite frame, built once at startup. Shields are the
+/// exception: they get damaged, so each one is re-rasterized whenever its
+/// cells no longer match the cached copy.
+pub struct SpriteTextures<'a> {
+    creator: &'a TextureCreator<WindowContext>,
+    frames: HashMap<String, Vec<Texture<'a>>>,
+    shields: HashMap<(i32, i32), (Sprite, Texture<'a>)>,
+}
+
+impl<'a> SpriteTextures<'a> {
+    pub fn new(creator: &'a TextureCreator<WindowContext>, bank: &SpriteBank) -> Result<Self, String> {
+        let mut frames = HashMap::new();
+        for name in bank.names() {
+            let def = bank.get(name).expect("name comes from the bank");
+            let textures = def.frames.iter()
+                .map(|f| rasterize(creator, f, def.color))
+                .collect::<Result<Vec<_>, _>>()?;
+            frames.insert(name.to_string(), textures);
+        }
+        Ok(Self { creator, frames, shields: HashMap::new() })
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, def: &SpriteDef, frame: usize, x: i32, y: i32) {
+        if let Some(texture) = self.frames.get(&def.name).and_then(|f| f.get(frame)) {
+            let _ = canvas.copy(texture, None, scaled(x, y, &def.frames[frame]));
+        }
+    }
+
+    fn draw_shield(&mut self, canvas: &mut Canvas<Window>, shield: &Shield) {
+        let key = (shield.x, shield.y);
+        let stale = self.shields.get(&key).is_none_or(|(cells, _)| *cells != shield.sprite);
+        if stale {
+            match rasterize(self.creator, &shield.sprite, shield.color) {
+                Ok(texture) => { self.shields.insert(key, (shield.sprite.clone(), texture)); }
+                Err(_) => return,
+            }
+        }
+        let (_, texture) = &self.shields[&key];
+        let _ = canvas.copy(texture, None, scaled(shield.x, shield.y, &shield.sprite));
+    }
+}
+
+pub fn draw_world(canvas: &mut Canvas<Window>, textures: &mut SpriteTextures, world: &World) {
+    let player = &world.player;
+    textures.draw(canvas, &player.sprite, 0, player.x, player.y);
+
+    for s in world.shields.iter().filter(|s| s.alive) {
+        textures.draw_shield(canvas, s);
+    }
+
+    let m = &world.mothership;
+    if m.alive {
+        textures.draw(canvas, m.sprite(), m.frame_ix(), m.x, m.y);
+    }
+
+    for a in world.aliens.iter().filter(|a| a.alive) {
+        textures.draw(canvas, a.sprite(), a.frame_ix(), a.x, a.y);
+    }
+
+    for c in world.capsules.iter().filter(|c| c.alive) {
+        textures.draw(canvas, c.sprite(), 0, c.x, c.y);
+    }
+
+    draw_bubble(canvas, world);
+    draw_bullets(canvas, textures, world);
+}
+
+/// Outline around the ship while a shield bubble is up.
+fn draw_bubble(canvas: &mut Canvas<Window>, world: &World) {
+    if !world.player.effects.active(PowerUpKind::ShieldBubble) {
+        return;
+    }
+    let [r, g, b] = BUBBLE_COLOR;
+    canvas.set_draw_color(Color::RGB(r, g, b));
+    let bubble = world.player.bubble();
+    let _ = canvas.draw_rect(Rect::new(bubble.x, bubble.y, bubble.w, bubble.h));
+}
+
+fn draw_bullets(canvas: &mut Canvas<Window>, textures: &SpriteTextures, world: &World) {
+    canvas.set_draw_color(Color::WHITE);
+    for b in world.player_bullets.iter().chain(&world.enemy_bullets).filter(|b| b.alive) {
+        match b.sprite() {
+            Some(def) => textures.draw(canvas, def, b.frame_ix(), b.x, b.y),
+            None => {
+                let _ = canvas.fill_rect(Rect::new(b.x, b.y, b.w, b.h));
+            }
+        }
+    }
+}
+
+/// One `fill_rect` per lit cell. This is how every sprite used to be drawn
+/// and is only kept as the baseline for `--bench-render`.
+pub fn draw_cells(canvas: &mut Canvas<Window>, design: &Sprite, [r, g, b]: [u8; 3], x: i32, y: i32) {
+    canvas.set_draw_color(Color::RGB(r, g, b));
+
+    for (row_idx, row) in design.iter().enumerate() {
+        for (col_idx, &pixel) in row.iter().enumerate() {
+            if pixel == 1 {
+                let rect = Rect::new(
+                    x + (col_idx as i32 * PIXEL as i32),
+                    y + (row_idx as i32 * PIXEL as i32),
+                    PIXEL,
+                    PIXEL,
+                );
+                let _ = canvas.fill_rect(rect);
+            }
+        }
+    }
+}
+
+/// `draw_world` through `draw_cells`, for `--bench-render`.
+pub fn draw_world_cells(canvas: &mut Canvas<Window>, world: &World) {
+    let player = &world.player;
+    draw_cells(canvas, &player.sprite.frames[0], player.sprite.color, player.x, player.y);
+
+    for s in world.shields.iter().filter(|s| s.alive) {
+        draw_cells(canvas, &s.sprite, s.color, s.x, s.y);
+    }
+
+    let m = &world.mothership;
+    if m.alive {
+        draw_cells(canvas, m.current_sprite(), m.sprite().color, m.x, m.y);
+    }
+
+    for a in world.aliens.iter().filter(|a| a.alive) {
+        draw_cells(canvas, a.current_sprite(), a.sprite().color, a.x, a.y);
+    }
+
+    for c in world.capsules.iter().filter(|c| c.alive) {
+        draw_cells(canvas, &c.sprite().frames[0], c.sprite().color, c.x, c.y);
+    }
+
+    draw_bubble(canvas, world);
+    for b in world.player_bullets.iter().chain(&world.enemy_bullets).filter(|b| b.alive) {
+        match b.sprite() {
+            Some(def) => draw_cells(canvas, &def.frames[b.frame_ix()], def.color, b.x, b.y),
+            None => {
+                canvas.set_draw_color(Color::WHITE);
+                let _ = canvas.fill_rect(Rect::new(b.x, b.y, b.w, b.h));
+            }
+        }
+    }
+}
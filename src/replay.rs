@@ -0,0 +1,450 @@
+use std::{
+    fs,
+    io::{
+        self,
+        Error,
+        ErrorKind,
+        Write,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use crate::{
+    clock::ticks_from_ms,
+    config::{
+        COOLDOWN_MS,
+        LIVES,
+        MAX_BULLETS,
+    },
+    sprites,
+    world::{
+        Input,
+        Loadout,
+        Weapon,
+        MAX_BULLET_SPEED,
+    },
+};
+
+// File layout, all integers little endian:
+//   magic "SIRP", u16 format version, u8 length + game version string,
+//   u64 seed, loadout, u64 data hash, u32 tick count, then (u8 flags, LEB128
+//   run length) pairs. The loadout is u8 lives, u8 max bullets, u16 cooldown
+//   ticks and u8 bullet speed. Version 3 has no data hash, version 2 only
+//   has the lives byte and version 1 has no loadout at all; versions 1 and 2
+//   used the classic weapon and version 1 three lives.
+const MAGIC: &[u8; 4] = b"SIRP";
+const FORMAT_VERSION: u16 = 4;
+/// Longest run a file may hold: a day of play. Guards against a corrupt
+/// tick count asking for gigabytes.
+const MAX_TICKS: usize = 24 * 60 * 60 * 60;
+/// Replays `save_new` will number from the same second before giving up.
+const MAX_SAME_STAMP: u32 = 1000;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+const LEFT: u8 = 1 << 0;
+const RIGHT: u8 = 1 << 1;
+const FIRE: u8 = 1 << 2;
+// Bit 3 marked a pause in older recordings and is ignored on playback.
+
+/// Seed, loadout and one input byte per simulation tick; enough to rebuild
+/// a run.
+pub struct Replay {
+    pub game_version: String,
+    pub seed: u64,
+    pub loadout: Loadout,
+    /// `data_hash` of the sprites and waves the run was played with; `None`
+    /// for files older than format 4.
+    pub data_hash: Option<u64>,
+    ticks: Vec<u8>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, loadout: Loadout, data_hash: u64) -> Self {
+        Self {
+            game_version: env!("CARGO_PKG_VERSION").to_string(),
+            seed,
+            loadout,
+            data_hash: Some(data_hash),
+            ticks: Vec::new(),
+        }
+    }
+
+    /// Appends the input of one tick.
+    pub fn record(&mut self, input: Input) {
+        let mut flags = 0;
+        if input.left { flags |= LEFT; }
+        if input.right { flags |= RIGHT; }
+        if input.fire { flags |= FIRE; }
+        self.ticks.push(flags);
+    }
+
+    pub fn len(&self) -> usize {
+        self.ticks.len()
+    }
+
+    pub fn input_at(&self, tick: usize) -> Option<Input> {
+        self.ticks.get(tick).map(|&flags| Input {
+            left: flags & LEFT != 0,
+            right: flags & RIGHT != 0,
+            fire: flags & FIRE != 0,
+        })
+    }
+
+    /// Saves into `dir` as `replay-<stamp>.sirp`, or `replay-<stamp>-2.sirp`
+    /// and so on when that name is taken, and returns the path. An existing
+    /// file is never overwritten.
+    pub fn save_new(&self, dir: &Path, stamp: u64) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let bytes = self.to_bytes();
+        for n in 1..=MAX_SAME_STAMP {
+            let name = match n {
+                1 => format!("replay-{}.sirp", stamp),
+                n => format!("replay-{}-{}.sirp", stamp, n),
+            };
+            let path = dir.join(name);
+            match write_new(&path, &bytes) {
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                result => return result.map(|()| path),
+            }
+        }
+        Err(Error::new(ErrorKind::AlreadyExists, format!("{} replays named replay-{} already", MAX_SAME_STAMP, stamp)))
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let version = self.game_version.as_bytes();
+        let mut out = Vec::with_capacity(32 + self.ticks.len() / 8);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        out.push(version.len().min(u8::MAX as usize) as u8);
+        out.extend_from_slice(&version[..version.len().min(u8::MAX as usize)]);
+        out.extend_from_slice(&self.seed.to_le_bytes());
+        let Loadout { lives, weapon } = self.loadout;
+        out.push(lives.min(u8::MAX as u32) as u8);
+        out.push(weapon.max_bullets.min(u8::MAX as u32) as u8);
+        out.extend_from_slice(&(weapon.cooldown.min(u16::MAX as u64) as u16).to_le_bytes());
+        out.push(weapon.bullet_speed.clamp(0, u8::MAX as i32) as u8);
+        out.extend_from_slice(&self.data_hash.unwrap_or(0).to_le_bytes());
+        out.extend_from_slice(&(self.ticks.len() as u32).to_le_bytes());
+
+        let mut i = 0;
+        while i < self.ticks.len() {
+            let flags = self.ticks[i];
+            let run = self.ticks[i..].iter().take_while(|&&f| f == flags).count();
+            out.push(flags);
+            write_varint(&mut out, run as u64);
+            i += run;
+        }
+        out
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let mut r = Reader { data: &data, pos: 0 };
+
+        if r.take(4)? != MAGIC {
+            return Err(invalid("not a replay file"));
+        }
+        let format = u16::from_le_bytes(r.take(2)?.try_into().unwrap());
+        if format == 0 || format > FORMAT_VERSION {
+            return Err(invalid(&format!("unsupported replay format {}", format)));
+        }
+        let version_len = r.take(1)?[0] as usize;
+        let game_version = String::from_utf8(r.take(version_len)?.to_vec())
+            .map_err(|_| invalid("game version is not utf-8"))?;
+        let seed = u64::from_le_bytes(r.take(8)?.try_into().unwrap());
+        let mut loadout = Loadout::default();
+        if format >= 2 {
+            loadout.lives = in_range(r.take(1)?[0] as u32, LIVES, "lives")?;
+        }
+        if format >= 3 {
+            let max_bullets = in_range(r.take(1)?[0] as u32, MAX_BULLETS, "max bullets")?;
+            let cooldown = u16::from_le_bytes(r.take(2)?.try_into().unwrap()) as u32;
+            let cooldown = in_range(cooldown, (0, ticks_from_ms(COOLDOWN_MS.1 as u64) as u32), "cooldown")? as u64;
+            let bullet_speed = in_range(r.take(1)?[0] as u32, (1, MAX_BULLET_SPEED as u32), "bullet speed")? as i32;
+            loadout.weapon = Weapon { max_bullets, cooldown, bullet_speed };
+        }
+        let data_hash = if format >= 4 { Some(u64::from_le_bytes(r.take(8)?.try_into().unwrap())) } else { None };
+        let count = u32::from_le_bytes(r.take(4)?.try_into().unwrap()) as usize;
+        if count > MAX_TICKS {
+            return Err(invalid(&format!("{} ticks is more than a replay can hold", count)));
+        }
+
+        // Grown run by run, so the header alone cannot make it allocate.
+        let mut ticks = Vec::new();
+        while ticks.len() < count {
+            let flags = r.take(1)?[0];
+            let run = r.varint()? as usize;
+            if run == 0 || ticks.len() + run > count {
+                return Err(invalid("corrupt input run"));
+            }
+            ticks.resize(ticks.len() + run, flags);
+        }
+
+        Ok(Self { game_version, seed, loadout, data_hash, ticks })
+    }
+}
+
+/// Fingerprint of the sprite files and the waves file a run is played with,
+/// so playback can tell when they changed since the recording. FNV-1a over
+/// each file's name and contents, as std's hasher may change between Rust
+/// releases.
+pub fn data_hash(sprites_dir: &Path, waves_path: &Path) -> io::Result<u64> {
+    let mut files = sprites::sprite_files(sprites_dir).map_err(|e| Error::other(e.to_string()))?;
+    files.push(waves_path.to_path_buf());
+
+    let mut hash = FNV_OFFSET;
+    for file in files {
+        let name = file.file_name().map(|n| n.as_encoded_bytes()).unwrap_or_default();
+        let contents = fs::read(&file)?;
+        for part in [name, &contents] {
+            for byte in (part.len() as u64).to_le_bytes().iter().chain(part) {
+                hash = (hash ^ *byte as u64).wrapping_mul(FNV_PRIME);
+            }
+        }
+    }
+    Ok(hash)
+}
+
+/// Creates `path` holding `bytes`, failing if it exists. A file left half
+/// written is removed.
+fn write_new(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut file = fs::OpenOptions::new().write(true).create_new(true).open(path)?;
+    let written = file.write_all(bytes).and_then(|()| file.sync_all());
+    if written.is_err() {
+        drop(file);
+        let _ = fs::remove_file(path);
+    }
+    written
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// The loadout as the config would allow it, so a replay cannot set up a
+/// game that could not be played.
+fn in_range(value: u32, (lo, hi): (u32, u32), what: &str) -> io::Result<u32> {
+    if (lo..=hi).contains(&value) {
+        Ok(value)
+    } else {
+        Err(invalid(&format!("{} must be between {} and {}, got {}", what, lo, hi, value)))
+    }
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        let end = self.pos + n;
+        let bytes = self.data.get(self.pos..end).ok_or_else(|| invalid("unexpected end of file"))?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn varint(&mut self) -> io::Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid("run length too long"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::world::FireMode;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sirp-test-{}-{}.sirp", std::process::id(), name))
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sirp-test-{}-{}", std::process::id(), name))
+    }
+
+    fn load_bytes(name: &str, bytes: &[u8]) -> io::Result<Replay> {
+        let path = temp_path(name);
+        fs::write(&path, bytes).unwrap();
+        let replay = Replay::load(&path);
+        fs::remove_file(&path).unwrap();
+        replay
+    }
+
+    /// A format 3 header up to and including the tick count.
+    fn header(lives: u8, max_bullets: u8, cooldown: u16, bullet_speed: u8, count: u32) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&3u16.to_le_bytes());
+        out.extend_from_slice(&[1, b'x']);
+        out.extend_from_slice(&9u64.to_le_bytes());
+        out.extend_from_slice(&[lives, max_bullets]);
+        out.extend_from_slice(&cooldown.to_le_bytes());
+        out.push(bullet_speed);
+        out.extend_from_slice(&count.to_le_bytes());
+        out
+    }
+
+    #[test]
+    fn round_trips_through_a_file() {
+        let loadout = Loadout { lives: 5, weapon: FireMode::Rapid.weapon() };
+        let mut replay = Replay::new(1234, loadout, 0xfeed);
+        let inputs = [
+            Input::default(),
+            Input { left: true, ..Input::default() },
+            Input { left: true, ..Input::default() },
+            Input { right: true, fire: true, ..Input::default() },
+        ];
+        for _ in 0..200 {
+            for input in inputs {
+                replay.record(input);
+            }
+        }
+
+        let dir = temp_dir("round-trip");
+        let path = replay.save_new(&dir, 1).unwrap();
+        let loaded = Replay::load(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.game_version, replay.game_version);
+        assert_eq!(loaded.seed, 1234);
+        assert_eq!(loaded.loadout, loadout);
+        assert_eq!(loaded.data_hash, Some(0xfeed));
+        assert_eq!(loaded.len(), replay.len());
+        for tick in 0..replay.len() {
+            assert_eq!(loaded.input_at(tick), replay.input_at(tick));
+        }
+        assert_eq!(loaded.input_at(replay.len()), None);
+    }
+
+    #[test]
+    fn saves_never_overwrite_each_other() {
+        let dir = temp_dir("numbered");
+        let mut first = Replay::new(1, Loadout::default(), 0);
+        first.record(Input { fire: true, ..Input::default() });
+        let second = Replay::new(2, Loadout::default(), 0);
+
+        let paths = [first.save_new(&dir, 7).unwrap(), second.save_new(&dir, 7).unwrap()];
+        let seeds = paths.each_ref().map(|p| Replay::load(p).unwrap().seed);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(paths.map(|p| p.file_name().unwrap().to_owned()), ["replay-7.sirp", "replay-7-2.sirp"]);
+        assert_eq!(seeds, [1, 2]);
+    }
+
+    #[test]
+    fn data_hash_follows_the_files() {
+        let dir = temp_dir("data");
+        let sprites_dir = dir.join("sprites");
+        let waves_path = dir.join("waves.toml");
+        fs::create_dir_all(&sprites_dir).unwrap();
+        fs::write(sprites_dir.join("a.txt"), "[a]\n#\n").unwrap();
+        fs::write(&waves_path, "[[wave]]\n").unwrap();
+
+        let hash = || data_hash(&sprites_dir, &waves_path).unwrap();
+        let original = hash();
+        let again = hash();
+        fs::write(sprites_dir.join("a.txt"), "[a]\n.\n").unwrap();
+        let sprite_changed = hash();
+        fs::write(sprites_dir.join("a.txt"), "[a]\n#\n").unwrap();
+        fs::write(sprites_dir.join("notes.md"), "not a sprite").unwrap();
+        let other_file = hash();
+        fs::write(&waves_path, "[[wave]]\n\n").unwrap();
+        let waves_changed = hash();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(original, again);
+        assert_ne!(original, sprite_changed);
+        assert_eq!(original, other_file);
+        assert_ne!(original, waves_changed);
+    }
+
+    #[test]
+    fn format_3_has_no_data_hash() {
+        let replay = load_bytes("format-3", &header(3, 1, 0, 6, 0)).unwrap();
+        assert_eq!(replay.data_hash, None);
+    }
+
+    #[test]
+    fn reads_format_1() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.push(0);
+        bytes.extend_from_slice(&7u64.to_le_bytes());
+        bytes.extend_from_slice(&3u32.to_le_bytes());
+        bytes.extend_from_slice(&[FIRE, 3]);
+
+        let replay = load_bytes("format-1", &bytes).unwrap();
+        assert_eq!(replay.loadout, Loadout::default());
+        assert_eq!(replay.len(), 3);
+        assert_eq!(replay.input_at(2), Some(Input { fire: true, ..Input::default() }));
+    }
+
+    #[test]
+    fn ignores_old_pause_flag() {
+        let mut bytes = header(3, 1, 0, 6, 1);
+        bytes.extend_from_slice(&[LEFT | 1 << 3, 1]);
+        let replay = load_bytes("pause-flag", &bytes).unwrap();
+        assert_eq!(replay.input_at(0), Some(Input { left: true, ..Input::default() }));
+    }
+
+    #[test]
+    fn rejects_a_huge_tick_count() {
+        let err = load_bytes("huge", &header(3, 1, 0, 6, u32::MAX)).err().unwrap();
+        assert!(err.to_string().contains("more than a replay can hold"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_runs_past_the_tick_count() {
+        let mut bytes = header(3, 1, 0, 6, 2);
+        bytes.extend_from_slice(&[0, 3]);
+        let err = load_bytes("long-run", &bytes).err().unwrap();
+        assert_eq!(err.to_string(), "corrupt input run");
+    }
+
+    #[test]
+    fn rejects_a_loadout_the_config_would_not_allow() {
+        let cases = [
+            ("lives", header(0, 1, 0, 6, 0), "lives must be between"),
+            ("no-bullets", header(3, 0, 0, 6, 0), "max bullets must be between"),
+            ("still", header(3, 1, 0, 0, 0), "bullet speed must be between"),
+            ("fast", header(3, 1, 0, MAX_BULLET_SPEED as u8 + 1, 0), "bullet speed must be between"),
+            ("cooldown", header(3, 1, u16::MAX, 6, 0), "cooldown must be between"),
+        ];
+        for (name, bytes, msg) in cases {
+            let err = load_bytes(name, &bytes).err().unwrap();
+            assert!(err.to_string().starts_with(msg), "{}: {}", name, err);
+        }
+    }
+
+    #[test]
+    fn rejects_a_truncated_file() {
+        let err = load_bytes("truncated", &header(3, 1, 0, 6, 5)).err().unwrap();
+        assert_eq!(err.to_string(), "unexpected end of file");
+    }
+}
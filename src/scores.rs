@@ -0,0 +1,407 @@
+//! The top-10 table, kept as TOML in the user's data directory, and the
+//! initials entry shown when a finished game makes it onto the table.
+//!
+//! The table is only written at wave clear, game over, naming and quit. A
+//! game in progress is kept on it under `---` until it gets initials, so a
+//! crash loses at most the current wave.
+
+use std::{
+    fmt,
+    fs,
+    io,
+    path::Path,
+    time::{
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+
+use sdl2::{
+    keyboard::Scancode,
+    pixels::Color,
+    render::Canvas,
+    video::Window,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::{
+    controls::Action,
+    persist,
+    text::{
+        FontSize,
+        Position,
+        TextCache,
+    },
+};
+
+pub const SCORES_FILE: &str = "scores.toml";
+/// Where versions before the table kept their single high score.
+pub const LEGACY_FILE: &str = "assets/highscore.txt";
+pub const MAX_ENTRIES: usize = 10;
+
+// Bump when the layout of the file changes.
+const FORMAT_VERSION: u32 = 1;
+const UNNAMED: &str = "---";
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScoreEntry {
+    pub initials: String,
+    pub score: i32,
+    pub wave: u32,
+    /// Day the game ended, `YYYY-MM-DD` in UTC.
+    pub date: String,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct ScoreFile {
+    version: u32,
+    #[serde(default, rename = "entry")]
+    entries: Vec<ScoreEntry>,
+}
+
+#[derive(Debug)]
+pub enum ScoreError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Version(u32),
+}
+
+impl fmt::Display for ScoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScoreError::Io(e) => write!(f, "{}", e),
+            ScoreError::Parse(e) => write!(f, "{}", e),
+            ScoreError::Version(v) => write!(f, "unsupported score file version {}, expected {}", v, FORMAT_VERSION),
+        }
+    }
+}
+
+/// Best scores first, at most `MAX_ENTRIES` of them.
+#[derive(Clone, Debug, Default)]
+pub struct ScoreTable {
+    entries: Vec<ScoreEntry>,
+    /// Entry of the game in progress, until it is named.
+    unnamed: Option<usize>,
+}
+
+impl ScoreTable {
+    /// Reads the table. A missing file is an empty table. If the file is
+    /// corrupt the backup from the previous save is used instead, and the
+    /// error is returned next to it so it can still be reported. The bad file
+    /// is moved to `<path>.corrupt` so the next save does not back it up over
+    /// the good copy. A file that cannot be read, or is from a newer version,
+    /// may still be good, so it is left alone and the error returned.
+    pub fn load(path: &Path) -> Result<(Self, Option<ScoreError>), ScoreError> {
+        match Self::read(path) {
+            Ok(table) => Ok((table, None)),
+            Err(e @ ScoreError::Parse(_)) => match Self::read(&persist::with_suffix(path, ".bak")) {
+                Ok(table) if !table.entries.is_empty() => {
+                    let _ = fs::rename(path, persist::with_suffix(path, ".corrupt"));
+                    Ok((table, Some(e)))
+                }
+                _ => Err(e),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read(path: &Path) -> Result<Self, ScoreError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(ScoreError::Io(e)),
+        };
+        let file: ScoreFile = toml::from_str(&text).map_err(ScoreError::Parse)?;
+        if file.version != FORMAT_VERSION {
+            return Err(ScoreError::Version(file.version));
+        }
+        let mut table = Self { entries: file.entries, unnamed: None };
+        table.entries.sort_by_key(|e| std::cmp::Reverse(e.score));
+        table.entries.truncate(MAX_ENTRIES);
+        Ok(table)
+    }
+
+    /// Puts the lone score older versions kept in `legacy` on the table,
+    /// without initials and dated by the file. Meant for when there is no
+    /// table file yet; returns whether anything was added.
+    pub fn import_legacy(&mut self, legacy: &Path) -> bool {
+        let Some(score) = fs::read_to_string(legacy).ok().and_then(|s| s.trim().parse::<i32>().ok()) else {
+            return false;
+        };
+        if !self.qualifies(score) {
+            return false;
+        }
+        let date = fs::metadata(legacy).and_then(|m| m.modified()).map(date_of).unwrap_or_else(|_| today());
+        let rank = self.entries.iter().position(|e| score > e.score).unwrap_or(self.entries.len());
+        self.entries.insert(rank, ScoreEntry { initials: UNNAMED.to_string(), score, wave: 0, date });
+        self.entries.truncate(MAX_ENTRIES);
+        true
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let file = ScoreFile { version: FORMAT_VERSION, entries: self.entries.clone() };
+        let text = toml::to_string(&file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        persist::write_atomic(path, text.as_bytes())
+    }
+
+    pub fn best(&self) -> i32 {
+        self.entries.first().map(|e| e.score).unwrap_or(0)
+    }
+
+    fn qualifies(&self, score: i32) -> bool {
+        score > 0 && (self.entries.len() < MAX_ENTRIES || self.entries.iter().any(|e| score > e.score))
+    }
+
+    /// Puts the game in progress on the table as `---`, or moves its entry
+    /// to the new score. Nothing happens if the score does not qualify.
+    pub fn record(&mut self, score: i32, wave: u32) {
+        if let Some(i) = self.unnamed.take() {
+            self.entries.remove(i);
+        }
+        if !self.qualifies(score) {
+            return;
+        }
+        // Below any equal score, so older entries keep their place.
+        let rank = self.entries.iter().position(|e| score > e.score).unwrap_or(self.entries.len());
+        self.entries.insert(rank, ScoreEntry { initials: UNNAMED.to_string(), score, wave, date: today() });
+        self.entries.truncate(MAX_ENTRIES);
+        self.unnamed = Some(rank);
+    }
+
+    /// Whether the game in progress is on the table waiting for initials.
+    pub fn has_unnamed(&self) -> bool {
+        self.unnamed.is_some()
+    }
+
+    pub fn name(&mut self, initials: String) {
+        if let Some(i) = self.unnamed.take() {
+            self.entries[i].initials = initials;
+        }
+    }
+
+    /// Leaves the current game's entry, if any, as it is. Call when a new
+    /// game starts.
+    pub fn end_game(&mut self) {
+        self.unnamed = None;
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, text: &mut TextCache, top: i32) {
+        text.draw(canvas, FontSize::Small, "High Scores", Position::BelowCenter(top));
+        if self.entries.is_empty() {
+            text.draw(canvas, FontSize::Small, "No scores yet", Position::BelowCenter(top + 40));
+        }
+        for (i, e) in self.entries.iter().enumerate() {
+            let line = format!("{:>2}. {:<3} {:>7}  W{:<3} {}", i + 1, e.initials, e.score, e.wave, e.date);
+            text.draw(canvas, FontSize::Small, &line, Position::BelowCenter(top + 40 + i as i32 * 24));
+        }
+    }
+}
+
+/// Three-letter initials picker. Letters can be typed directly; the arrow
+/// keys move between slots and cycle the letter under the cursor.
+pub struct InitialsEntry {
+    letters: [u8; 3],
+    cursor: usize,
+}
+
+impl Default for InitialsEntry {
+    fn default() -> Self {
+        Self { letters: *b"AAA", cursor: 0 }
+    }
+}
+
+impl InitialsEntry {
+    /// Returns the initials once they are confirmed.
+    pub fn key_down(&mut self, key: Option<Scancode>, action: Option<Action>) -> Option<String> {
+        let letter = key.map(|k| k.name()).filter(|n| n.len() == 1 && n.as_bytes()[0].is_ascii_uppercase());
+        match (key, letter, action) {
+            (_, Some(name), _) => {
+                self.letters[self.cursor] = name.as_bytes()[0];
+                self.cursor = (self.cursor + 1).min(2);
+            }
+            (Some(Scancode::Left | Scancode::Backspace), _, _) => self.cursor = self.cursor.saturating_sub(1),
+            (Some(Scancode::Right), _, _) => self.cursor = (self.cursor + 1).min(2),
+            (Some(Scancode::Up), _, _) => self.cycle(1),
+            (Some(Scancode::Down), _, _) => self.cycle(25),
+            (_, _, Some(Action::Confirm)) => return Some(String::from_utf8_lossy(&self.letters).into_owned()),
+            _ => {}
+        }
+        None
+    }
+
+    fn cycle(&mut self, by: u8) {
+        let c = &mut self.letters[self.cursor];
+        *c = b'A' + (*c - b'A' + by) % 26;
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, text: &mut TextCache) {
+        text.draw(canvas, FontSize::Big, "New High Score!", Position::BelowCenter(-120));
+        text.draw(canvas, FontSize::Small, "Enter your initials", Position::BelowCenter(-60));
+
+        let highlight = Color::RGB(255, 220, 60);
+        for (i, &c) in self.letters.iter().enumerate() {
+            let color = if i == self.cursor { highlight } else { Color::WHITE };
+            let letter = (c as char).to_string();
+            text.draw_colored(canvas, FontSize::Big, &letter, color, Position::CenterOffset(-60 + i as i32 * 60, 0));
+        }
+        text.draw(canvas, FontSize::Small, "Type or arrows, Enter to save", Position::BelowCenter(80));
+    }
+}
+
+/// Today's date in UTC as `YYYY-MM-DD`.
+fn today() -> String {
+    date_of(SystemTime::now())
+}
+
+fn date_of(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (y, m, d) = civil_from_days((secs / 86_400) as i64);
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+// Days since 1970-01-01 to a proleptic Gregorian date (Howard Hinnant's
+// `civil_from_days`).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A fresh directory for one test, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("scores-test-{}-{}", std::process::id(), name));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn file(&self) -> PathBuf {
+            self.0.join(SCORES_FILE)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn table(scores: &[i32]) -> ScoreTable {
+        let mut table = ScoreTable::default();
+        for &score in scores {
+            table.record(score, 1);
+            table.name("ABC".to_string());
+        }
+        table
+    }
+
+    fn scores(table: &ScoreTable) -> Vec<i32> {
+        table.entries.iter().map(|e| e.score).collect()
+    }
+
+    #[test]
+    fn missing_file_is_an_empty_table() {
+        let dir = TempDir::new("missing");
+        let (table, error) = ScoreTable::load(&dir.file()).unwrap();
+        assert!(table.entries.is_empty());
+        assert!(error.is_none());
+    }
+
+    #[test]
+    fn save_keeps_the_previous_file_as_backup() {
+        let dir = TempDir::new("rotate");
+        let path = dir.file();
+        table(&[100]).save(&path).unwrap();
+        table(&[200, 100]).save(&path).unwrap();
+
+        assert_eq!(scores(&ScoreTable::load(&path).unwrap().0), [200, 100]);
+        assert_eq!(scores(&ScoreTable::read(&persist::with_suffix(&path, ".bak")).unwrap()), [100]);
+        assert!(!persist::with_suffix(&path, ".tmp").exists());
+    }
+
+    #[test]
+    fn corrupt_file_falls_back_to_the_backup() {
+        let dir = TempDir::new("corrupt");
+        let path = dir.file();
+        table(&[100]).save(&path).unwrap();
+        table(&[200, 100]).save(&path).unwrap();
+        fs::write(&path, "version = 1\n[[entry]\n").unwrap();
+
+        let (table, error) = ScoreTable::load(&path).unwrap();
+        assert_eq!(scores(&table), [100]);
+        assert!(matches!(error, Some(ScoreError::Parse(_))));
+        assert!(!path.exists());
+        assert_eq!(fs::read_to_string(persist::with_suffix(&path, ".corrupt")).unwrap(), "version = 1\n[[entry]\n");
+    }
+
+    #[test]
+    fn newer_file_is_left_alone() {
+        let dir = TempDir::new("newer");
+        let path = dir.file();
+        table(&[100]).save(&path).unwrap();
+        table(&[200, 100]).save(&path).unwrap();
+        let newer = format!("version = {}\n", FORMAT_VERSION + 1);
+        fs::write(&path, &newer).unwrap();
+
+        assert!(matches!(ScoreTable::load(&path), Err(ScoreError::Version(v)) if v == FORMAT_VERSION + 1));
+        assert_eq!(fs::read_to_string(&path).unwrap(), newer);
+        assert!(!persist::with_suffix(&path, ".corrupt").exists());
+    }
+
+    #[test]
+    fn unreadable_file_is_left_alone() {
+        let dir = TempDir::new("unreadable");
+        let path = dir.file();
+        table(&[100]).save(&persist::with_suffix(&path, ".bak")).unwrap();
+        // A directory in its place cannot be read as a file.
+        fs::create_dir(&path).unwrap();
+
+        assert!(matches!(ScoreTable::load(&path), Err(ScoreError::Io(_))));
+        assert!(path.is_dir());
+        assert!(!persist::with_suffix(&path, ".corrupt").exists());
+    }
+
+    #[test]
+    fn imports_the_legacy_high_score() {
+        let dir = TempDir::new("legacy");
+        let legacy = dir.0.join("highscore.txt");
+        let mut table = ScoreTable::default();
+        assert!(!table.import_legacy(&legacy));
+
+        fs::write(&legacy, "1230\n").unwrap();
+        assert!(table.import_legacy(&legacy));
+        assert_eq!(scores(&table), [1230]);
+        assert_eq!(table.entries[0].initials, UNNAMED);
+        assert!(!table.has_unnamed());
+
+        fs::write(&legacy, "garbage").unwrap();
+        assert!(!table.import_legacy(&legacy));
+    }
+
+    #[test]
+    fn keeps_the_best_ten() {
+        let table = table(&[5, 50, 20, 70, 10, 90, 30, 60, 40, 80, 100, 1]);
+        assert_eq!(scores(&table), [100, 90, 80, 70, 60, 50, 40, 30, 20, 10]);
+    }
+}
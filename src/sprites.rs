@@ -0,0 +1,265 @@
+//! Sprite files are plain text. `[name]` starts a sprite, an optional
+//! `color = #rrggbb` line follows, then one or more frames drawn with `#`
+//! (lit) and `.` (empty), separated by blank lines. Lines starting with `;`
+//! are comments.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+    rc::Rc,
+};
+
+pub const SPRITES_DIR: &str = "assets/sprites";
+
+/// One frame as a grid of lit (1) and empty (0) cells.
+pub type Sprite = Vec<Vec<i32>>;
+
+/// A named sprite with all of its animation frames. Every frame has the
+/// same size.
+#[derive(Debug)]
+pub struct SpriteDef {
+    pub name: String,
+    pub color: [u8; 3],
+    pub frames: Vec<Sprite>,
+    /// Line of the `[name]` header in the file it came from.
+    pub line: usize,
+}
+
+impl SpriteDef {
+    /// Width and height of a frame in cells.
+    pub fn cells(&self) -> (i32, i32) {
+        let first = &self.frames[0];
+        (first.first().map(|r| r.len()).unwrap_or(0) as i32, first.len() as i32)
+    }
+}
+
+#[derive(Debug)]
+pub enum SpriteError {
+    Io(PathBuf, std::io::Error),
+    Parse { file: PathBuf, line: usize, msg: String },
+    Missing(String),
+}
+
+impl fmt::Display for SpriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpriteError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            SpriteError::Parse { file, line, msg } => write!(f, "{}:{}: {}", file.display(), line, msg),
+            SpriteError::Missing(name) => write!(f, "no sprite named \"{}\" in {}", name, SPRITES_DIR),
+        }
+    }
+}
+
+/// Every sprite found under the sprites directory, by name.
+#[derive(Clone, Default)]
+pub struct SpriteBank {
+    sprites: HashMap<String, Rc<SpriteDef>>,
+}
+
+impl SpriteBank {
+    /// Loads every `.txt` file in `dir`, in file name order.
+    pub fn load_dir(dir: &Path) -> Result<Self, SpriteError> {
+        let mut bank = Self::default();
+        for file in sprite_files(dir)? {
+            let text = fs::read_to_string(&file).map_err(|e| SpriteError::Io(file.clone(), e))?;
+            for def in parse(&text).map_err(|(line, msg)| SpriteError::Parse { file: file.clone(), line, msg })? {
+                if bank.sprites.contains_key(&def.name) {
+                    return Err(SpriteError::Parse { file, line: def.line, msg: format!("sprite \"{}\" is defined twice", def.name) });
+                }
+                bank.sprites.insert(def.name.clone(), Rc::new(def));
+            }
+        }
+        Ok(bank)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Rc<SpriteDef>> {
+        self.sprites.get(name)
+    }
+
+    /// Like `get`, for sprites the game cannot run without.
+    pub fn require(&self, name: &str) -> Result<&Rc<SpriteDef>, SpriteError> {
+        self.get(name).ok_or_else(|| SpriteError::Missing(name.to_string()))
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.sprites.keys().map(|k| k.as_str()).collect();
+        names.sort();
+        names
+    }
+}
+
+/// The sprite files in `dir`, in the order `SpriteBank::load_dir` reads them.
+pub fn sprite_files(dir: &Path) -> Result<Vec<PathBuf>, SpriteError> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| SpriteError::Io(dir.to_path_buf(), e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "txt"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+// Parses one file into sprites. Errors carry the 1-based line number.
+fn parse(text: &str) -> Result<Vec<SpriteDef>, (usize, String)> {
+    let mut defs: Vec<SpriteDef> = Vec::new();
+    let mut frame: Sprite = Vec::new();
+
+    fn finish_frame(defs: &mut [SpriteDef], frame: &mut Sprite, line: usize) -> Result<(), (usize, String)> {
+        if frame.is_empty() {
+            return Ok(());
+        }
+        let def = defs.last_mut().ok_or((line, "frame rows before any [name] header".to_string()))?;
+        if let Some(first) = def.frames.first()
+            && (first.len() != frame.len() || first[0].len() != frame[0].len())
+        {
+            return Err((line, format!(
+                "frame {} of \"{}\" is {}x{}, expected {}x{} like the first frame",
+                def.frames.len() + 1, def.name, frame[0].len(), frame.len(), first[0].len(), first.len(),
+            )));
+        }
+        def.frames.push(std::mem::take(frame));
+        Ok(())
+    }
+
+    for (i, raw) in text.lines().enumerate() {
+        let n = i + 1;
+        let line = raw.trim_end();
+
+        if line.trim_start().starts_with(';') {
+            continue;
+        }
+        if line.trim().is_empty() {
+            finish_frame(&mut defs, &mut frame, n)?;
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix('[') {
+            finish_frame(&mut defs, &mut frame, n)?;
+            if let Some(def) = defs.last() && def.frames.is_empty() {
+                return Err((def.line, format!("sprite \"{}\" has no frames", def.name)));
+            }
+            let name = rest.strip_suffix(']').ok_or((n, "missing `]` after sprite name".to_string()))?.trim();
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err((n, format!("bad sprite name \"{}\", use letters, digits and `_`", name)));
+            }
+            defs.push(SpriteDef { name: name.to_string(), color: [255, 255, 255], frames: Vec::new(), line: n });
+            continue;
+        }
+        if let Some(value) = line.strip_prefix("color") {
+            let def = defs.last_mut().ok_or((n, "`color` before any [name] header".to_string()))?;
+            if !frame.is_empty() || !def.frames.is_empty() {
+                return Err((n, "`color` must come before the first frame".to_string()));
+            }
+            let value = value.trim().trim_start_matches('=').trim();
+            def.color = parse_color(value).ok_or((n, format!("bad color \"{}\", expected #rrggbb", value)))?;
+            continue;
+        }
+
+        let row = line.chars()
+            .map(|c| match c {
+                '#' => Ok(1),
+                '.' => Ok(0),
+                other => Err((n, format!("unexpected `{}` in frame, use `#` for lit and `.` for empty", other))),
+            })
+            .collect::<Result<Vec<i32>, _>>()?;
+        if let Some(first) = frame.first()
+            && first.len() != row.len()
+        {
+            return Err((n, format!("row is {} cells wide, the rows above are {}", row.len(), first.len())));
+        }
+        frame.push(row);
+    }
+
+    finish_frame(&mut defs, &mut frame, text.lines().count())?;
+    if let Some(def) = defs.last() && def.frames.is_empty() {
+        return Err((def.line, format!("sprite \"{}\" has no frames", def.name)));
+    }
+    Ok(defs)
+}
+
+fn parse_color(s: &str) -> Option<[u8; 3]> {
+    let hex = s.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> (usize, String) {
+        parse(text).err().unwrap()
+    }
+
+    #[test]
+    fn parses_frames_and_color() {
+        let defs = parse("; a comment\n[blip]\ncolor = #ff8000\n#.\n.#\n\n.#\n#.\n").unwrap();
+        assert_eq!(defs.len(), 1);
+        let blip = &defs[0];
+        assert_eq!(blip.name, "blip");
+        assert_eq!(blip.color, [255, 128, 0]);
+        assert_eq!(blip.frames, [vec![vec![1, 0], vec![0, 1]], vec![vec![0, 1], vec![1, 0]]]);
+        assert_eq!(blip.cells(), (2, 2));
+    }
+
+    #[test]
+    fn color_defaults_to_white() {
+        let defs = parse("[a]\n#\n[b]\n.\n").unwrap();
+        assert_eq!(defs.iter().map(|d| (d.name.as_str(), d.color)).collect::<Vec<_>>(),
+                   [("a", [255, 255, 255]), ("b", [255, 255, 255])]);
+    }
+
+    #[test]
+    fn reports_the_line_at_fault() {
+        let cases = [
+            ("#.\n", 1, "frame rows before any [name] header"),
+            ("[a\n#\n", 1, "missing `]` after sprite name"),
+            ("[a b]\n#\n", 1, "bad sprite name \"a b\", use letters, digits and `_`"),
+            ("color = #fff\n", 1, "`color` before any [name] header"),
+            ("[a]\ncolor = #12345g\n#\n", 2, "bad color \"#12345g\", expected #rrggbb"),
+            ("[a]\n#\ncolor = #ffffff\n", 3, "`color` must come before the first frame"),
+            ("[a]\n#.\n#x\n", 3, "unexpected `x` in frame, use `#` for lit and `.` for empty"),
+            ("[a]\n##\n#\n", 3, "row is 1 cells wide, the rows above are 2"),
+            ("[a]\n##\n\n#\n", 4, "frame 2 of \"a\" is 1x1, expected 2x1 like the first frame"),
+            ("[a]\n\n[b]\n#\n", 1, "sprite \"a\" has no frames"),
+            ("[a]\n#\n[b]\n", 3, "sprite \"b\" has no frames"),
+        ];
+        for (text, line, msg) in cases {
+            assert_eq!(error(text), (line, msg.to_string()), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn loads_the_shipped_sprites() {
+        let bank = SpriteBank::load_dir(Path::new(SPRITES_DIR)).unwrap();
+        for name in ["spaceship", "shield", "mothership", "alien_1", "alien_2"] {
+            assert!(bank.require(name).is_ok(), "{}", name);
+        }
+        assert_eq!(bank.require("nope").unwrap_err().to_string(), "no sprite named \"nope\" in assets/sprites");
+    }
+
+    #[test]
+    fn load_errors_name_the_file() {
+        let dir = std::env::temp_dir().join(format!("sprites-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "[dup]\n#\n").unwrap();
+        fs::write(dir.join("b.txt"), "; again\n[dup]\n#\n").unwrap();
+        let duplicate = SpriteBank::load_dir(&dir).err().unwrap().to_string();
+        fs::write(dir.join("b.txt"), "[other]\n#?\n").unwrap();
+        let bad_row = SpriteBank::load_dir(&dir).err().unwrap().to_string();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(duplicate, format!("{}:2: sprite \"dup\" is defined twice", dir.join("b.txt").display()));
+        assert_eq!(
+            bad_row,
+            format!("{}:2: unexpected `?` in frame, use `#` for lit and `.` for empty", dir.join("b.txt").display()),
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+
+use sdl2::{
+    pixels::Color,
+    rect::Rect,
+    render::{
+        Canvas,
+        Texture,
+        TextureCreator,
+        TextureQuery,
+    },
+    ttf::Font,
+    video::{
+        Window,
+        WindowContext,
+    },
+};
+
+use crate::world::{
+    WINDOW_H,
+    WINDOW_W,
+};
+
+/// Frames a string may go undrawn before its texture is dropped. Keeps
+/// static labels cached while old "Score: n" strings fall out.
+const EVICT_AFTER_FRAMES: u64 = 120;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FontSize {
+    Big,
+    Small,
+}
+
+pub enum Position {
+    Center,
+    TopLeft,
+    #[allow(dead_code)]
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    /// Horizontally centred, `n` pixels below the middle of the window.
+    BelowCenter(i32),
+    /// Centred on the middle of the window moved by `(dx, dy)`.
+    CenterOffset(i32, i32),
+    /// Top-left corner at `(x, y)`.
+    At(i32, i32),
+}
+
+type Key = (FontSize, String, [u8; 4]);
+
+struct Entry<'a> {
+    texture: Texture<'a>,
+    last_used: u64,
+}
+
+/// Rendered strings, kept across frames keyed by (font, text, color) so a
+/// label is only rasterized again when its text changes.
+pub struct TextCache<'a, 'ttf> {
+    creator: &'a TextureCreator<WindowContext>,
+    big: Font<'ttf, 'static>,
+    small: Font<'ttf, 'static>,
+    entries: HashMap<Key, Entry<'a>>,
+    failed: HashSet<Key>,
+    frame: u64,
+}
+
+impl<'a, 'ttf> TextCache<'a, 'ttf> {
+    pub fn new(creator: &'a TextureCreator<WindowContext>, big: Font<'ttf, 'static>, small: Font<'ttf, 'static>) -> Self {
+        Self { creator, big, small, entries: HashMap::new(), failed: HashSet::new(), frame: 0 }
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas<Window>, font: FontSize, text: &str, position: Position) {
+        self.draw_colored(canvas, font, text, Color::WHITE, position);
+    }
+
+    /// Draws `text`, rendering it first if it isn't cached. A string that
+    /// fails to render is reported once and then skipped.
+    pub fn draw_colored(&mut self, canvas: &mut Canvas<Window>, font: FontSize, text: &str, color: Color, position: Position) {
+        let key = (font, text.to_string(), [color.r, color.g, color.b, color.a]);
+        if self.failed.contains(&key) {
+            return;
+        }
+
+        if !self.entries.contains_key(&key) {
+            match self.render(font, text, color) {
+                Ok(texture) => { self.entries.insert(key.clone(), Entry { texture, last_used: self.frame }); }
+                Err(e) => {
+                    eprintln!("cannot render text \"{}\": {}", text, e);
+                    self.failed.insert(key);
+                    return;
+                }
+            }
+        }
+
+        let entry = self.entries.get_mut(&key).expect("inserted above");
+        entry.last_used = self.frame;
+        let TextureQuery { width: text_w, height: text_h, .. } = entry.texture.query();
+        let target = match position {
+            Position::Center => Rect::new(
+                (WINDOW_W - text_w as i32) / 2,
+                (WINDOW_H - text_h as i32) / 2,
+                text_w,
+                text_h,
+            ),
+            Position::TopLeft => Rect::new(10, 15, text_w, text_h),
+            Position::TopRight => Rect::new(WINDOW_W - 10 - text_w as i32, 15, text_w, text_h),
+            Position::BottomLeft => Rect::new(10, WINDOW_H * 4 / 5, text_w, text_h),
+            Position::BottomRight => Rect::new(WINDOW_W - 10 - text_w as i32, WINDOW_H * 4 / 5, text_w, text_h),
+            Position::CenterOffset(dx, dy) => Rect::new(
+                (WINDOW_W - text_w as i32) / 2 + dx,
+                (WINDOW_H - text_h as i32) / 2 + dy,
+                text_w,
+                text_h,
+            ),
+            Position::At(x, y) => Rect::new(x, y, text_w, text_h),
+            Position::BelowCenter(n) => Rect::new(
+                (WINDOW_W - text_w as i32) / 2,
+                WINDOW_H / 2 + n,
+                text_w,
+                text_h,
+            ),
+        };
+
+        let _ = canvas.copy(&entry.texture, None, Some(target));
+    }
+
+    /// Call once per presented frame to drop strings that stopped being drawn.
+    pub fn end_frame(&mut self) {
+        let frame = self.frame;
+        self.entries.retain(|_, e| frame - e.last_used <= EVICT_AFTER_FRAMES);
+        self.frame += 1;
+    }
+
+    fn render(&self, font: FontSize, text: &str, color: Color) -> Result<Texture<'a>, String> {
+        let font = match font {
+            FontSize::Big => &self.big,
+            FontSize::Small => &self.small,
+        };
+        let surface = font.render(text).blended(color).map_err(|e| e.to_string())?;
+        self.creator.create_texture_from_surface(&surface).map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,500 @@
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    fmt,
+    fs,
+    path::Path,
+    rc::Rc,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    powerups::{
+        DropTable,
+        PowerUpKind,
+    },
+    sprites::SpriteBank,
+    world::{
+        AlienKind,
+        ShotCollision,
+        ShotKind,
+        PIXEL,
+        SHIELD_Y,
+        WINDOW_W,
+    },
+};
+
+pub const WAVES_PATH: &str = "assets/waves/waves.toml";
+const MAX_MOTHERSHIP_SPEED: i32 = 20;
+
+/// One fleet layout as written in the waves file.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WaveDef {
+    /// Alien kind, or bare sprite, of each row, top to bottom.
+    pub rows: Vec<String>,
+    pub columns: u32,
+    /// Y of the top row in pixels.
+    pub start_height: i32,
+    /// Fleet step interval at full strength; it speeds up to a third of this
+    /// as the fleet is thinned out.
+    pub step_interval_ms: u64,
+    pub fire_interval_ms: u64,
+    /// Pixels the fleet moves down each time it bounces off an edge.
+    pub drop: i32,
+    /// Power-ups this wave drops instead of the file's `[drops]` table.
+    #[serde(default)]
+    pub drops: Option<DropTable>,
+}
+
+/// An alien kind as written in the `[aliens]` table.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct KindDef {
+    sprite: String,
+    score: u32,
+    hit_points: u32,
+    fire_rate: f32,
+    bullet: ShotKind,
+}
+
+impl Default for KindDef {
+    fn default() -> Self {
+        Self {
+            sprite: String::new(),
+            score: AlienKind::DEFAULT_SCORE as u32,
+            hit_points: 1,
+            fire_rate: 1.0,
+            bullet: ShotKind::default(),
+        }
+    }
+}
+
+/// Settings for one enemy shot type.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShotDef {
+    /// Multiplier on the difficulty's `bullet_speed`.
+    pub speed: f32,
+    /// What happens when one of the player's shots meets it.
+    pub collision: ShotCollision,
+}
+
+impl Default for ShotDef {
+    fn default() -> Self {
+        Self { speed: 1.0, collision: ShotCollision::Cancel }
+    }
+}
+
+/// The `[shots]` table: one entry per enemy shot type.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Shots {
+    pub rolling: ShotDef,
+    pub plunger: ShotDef,
+    pub squiggly: ShotDef,
+}
+
+impl Default for Shots {
+    fn default() -> Self {
+        Self {
+            rolling: ShotDef::default(),
+            plunger: ShotDef::default(),
+            squiggly: ShotDef { speed: 1.25, collision: ShotCollision::Pierce },
+        }
+    }
+}
+
+impl Shots {
+    pub fn get(&self, kind: ShotKind) -> &ShotDef {
+        match kind {
+            ShotKind::Rolling => &self.rolling,
+            ShotKind::Plunger => &self.plunger,
+            ShotKind::Squiggly => &self.squiggly,
+        }
+    }
+}
+
+/// When the mothership may fly over and how fast, from `[mothership]`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MothershipDef {
+    /// No trip starts while fewer aliens than this are left.
+    pub min_aliens: u32,
+    /// Range the wait before the first trip of a run is picked from.
+    pub first_delay_ms: [u64; 2],
+    /// Range the wait between later trips is picked from.
+    pub delay_ms: [u64; 2],
+    /// Pixels per tick.
+    pub speed: i32,
+}
+
+impl Default for MothershipDef {
+    fn default() -> Self {
+        Self { min_aliens: 8, first_delay_ms: [4000, 7000], delay_ms: [8000, 14000], speed: 5 }
+    }
+}
+
+/// A value that moves by `per_wave` for every wave past the first and stops
+/// at `limit`, whichever direction it is heading.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Curve {
+    pub base: f32,
+    pub per_wave: f32,
+    pub limit: f32,
+}
+
+impl Curve {
+    const fn new(base: f32, per_wave: f32, limit: f32) -> Self {
+        Self { base, per_wave, limit }
+    }
+
+    pub fn at(&self, wave_level: u32) -> f32 {
+        let v = self.base + self.per_wave * wave_level.saturating_sub(1) as f32;
+        if self.per_wave >= 0.0 { v.min(self.limit) } else { v.max(self.limit) }
+    }
+}
+
+/// How later waves get harder than the layout in their `[[wave]]` entry.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Difficulty {
+    /// Pixels added to the fleet's `start_height`.
+    pub start_offset: Curve,
+    /// Multiplier on `step_interval_ms`.
+    pub step_factor: Curve,
+    /// Multiplier on `fire_interval_ms`.
+    pub fire_factor: Curve,
+    /// Enemy bullet speed in pixels per tick.
+    pub bullet_speed: Curve,
+    /// Enemy bullets allowed on screen at once.
+    pub max_enemy_shots: Curve,
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Self {
+            start_offset: Curve::new(0.0, 10.0, 80.0),
+            step_factor: Curve::new(1.0, -0.06, 0.4),
+            fire_factor: Curve::new(1.0, -0.08, 0.35),
+            bullet_speed: Curve::new(5.0, 0.5, 9.0),
+            max_enemy_shots: Curve::new(3.0, 0.5, 8.0),
+        }
+    }
+}
+
+/// Everything the waves file describes.
+#[derive(Clone, Debug)]
+pub struct Waves {
+    pub waves: Vec<WaveDef>,
+    pub difficulty: Difficulty,
+    /// Drop table for waves without their own.
+    pub drops: DropTable,
+    /// The `[aliens]` table by name, with sprites resolved.
+    pub kinds: HashMap<String, Rc<AlienKind>>,
+    pub shots: Shots,
+    pub mothership: MothershipDef,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WaveFile {
+    wave: Vec<WaveDef>,
+    #[serde(default)]
+    difficulty: Difficulty,
+    #[serde(default)]
+    drops: DropTable,
+    #[serde(default)]
+    aliens: BTreeMap<String, KindDef>,
+    #[serde(default)]
+    shots: Shots,
+    #[serde(default)]
+    mothership: MothershipDef,
+}
+
+#[derive(Debug)]
+pub enum WaveError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Invalid { section: String, field: &'static str, msg: String },
+}
+
+impl fmt::Display for WaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WaveError::Io(e) => write!(f, "{}", e),
+            WaveError::Parse(e) => write!(f, "{}", e),
+            WaveError::Invalid { section, field, msg } => write!(f, "{}: `{}` {}", section, field, msg),
+        }
+    }
+}
+
+/// Reads and validates the wave list. Waves are played in file order and
+/// loop back to the first once the list runs out.
+pub fn load(path: &Path, sprites: &SpriteBank) -> Result<Waves, WaveError> {
+    let text = fs::read_to_string(path).map_err(WaveError::Io)?;
+    parse(&text, sprites)
+}
+
+fn parse(text: &str, sprites: &SpriteBank) -> Result<Waves, WaveError> {
+    let file: WaveFile = toml::from_str(text).map_err(WaveError::Parse)?;
+
+    if file.wave.is_empty() {
+        return Err(WaveError::Invalid { section: "file".into(), field: "wave", msg: "at least one [[wave]] is required".into() });
+    }
+    let mut kinds = HashMap::new();
+    for (name, def) in &file.aliens {
+        let kind = resolve_kind(def, sprites)
+            .map_err(|(field, msg)| WaveError::Invalid { section: format!("aliens.{}", name), field, msg })?;
+        kinds.insert(name.clone(), Rc::new(kind));
+    }
+    for (i, def) in file.wave.iter().enumerate() {
+        validate(def, sprites, &kinds)
+            .map_err(|(field, msg)| WaveError::Invalid { section: format!("wave {}", i + 1), field, msg })?;
+    }
+    validate_difficulty(&file.difficulty)
+        .map_err(|(field, msg)| WaveError::Invalid { section: "difficulty".into(), field, msg })?;
+    validate_drops(&file.drops)
+        .map_err(|(field, msg)| WaveError::Invalid { section: "drops".into(), field, msg })?;
+    validate_mothership(&file.mothership)
+        .map_err(|(field, msg)| WaveError::Invalid { section: "mothership".into(), field, msg })?;
+    for kind in ShotKind::ALL {
+        validate_shot(file.shots.get(kind))
+            .map_err(|(field, msg)| WaveError::Invalid { section: format!("shots.{}", kind.name()), field, msg })?;
+    }
+
+    Ok(Waves { waves: file.wave, difficulty: file.difficulty, drops: file.drops, kinds, shots: file.shots, mothership: file.mothership })
+}
+
+fn validate_mothership(m: &MothershipDef) -> Result<(), (&'static str, String)> {
+    for (field, [lo, hi]) in [("first_delay_ms", m.first_delay_ms), ("delay_ms", m.delay_ms)] {
+        if lo > hi {
+            return Err((field, format!("must be [shortest, longest], got [{}, {}]", lo, hi)));
+        }
+    }
+    if !(1..=MAX_MOTHERSHIP_SPEED).contains(&m.speed) {
+        return Err(("speed", format!("must be between 1 and {}, got {}", MAX_MOTHERSHIP_SPEED, m.speed)));
+    }
+    Ok(())
+}
+
+fn validate_shot(def: &ShotDef) -> Result<(), (&'static str, String)> {
+    if !def.speed.is_finite() || def.speed <= 0.0 {
+        return Err(("speed", format!("must be greater than 0, got {}", def.speed)));
+    }
+    Ok(())
+}
+
+fn resolve_kind(def: &KindDef, sprites: &SpriteBank) -> Result<AlienKind, (&'static str, String)> {
+    if def.sprite.is_empty() {
+        return Err(("sprite", "is required".into()));
+    }
+    let sprite = sprites.get(&def.sprite).ok_or_else(|| {
+        ("sprite", format!("unknown sprite \"{}\" (have {})", def.sprite, sprites.names().join(", ")))
+    })?;
+    if def.hit_points == 0 {
+        return Err(("hit_points", "must be at least 1".into()));
+    }
+    if !def.fire_rate.is_finite() || def.fire_rate < 0.0 {
+        return Err(("fire_rate", format!("must be 0 or more, got {}", def.fire_rate)));
+    }
+    Ok(AlienKind {
+        sprite: sprite.clone(),
+        score: def.score as i32,
+        hit_points: def.hit_points,
+        fire_rate: def.fire_rate,
+        bullet: def.bullet,
+    })
+}
+
+fn validate_drops(t: &DropTable) -> Result<(), (&'static str, String)> {
+    for (field, chance) in [("chance", t.chance), ("mothership_chance", t.mothership_chance)] {
+        if chance > 100 {
+            return Err((field, format!("is a percentage and must be at most 100, got {}", chance)));
+        }
+    }
+    if t.total_weight() == 0 && (t.chance > 0 || t.mothership_chance > 0) {
+        let names: Vec<&str> = PowerUpKind::ALL.iter().map(|k| k.name()).collect();
+        return Err(("chance", format!("is set but every weight is 0 ({})", names.join(", "))));
+    }
+    Ok(())
+}
+
+fn validate_difficulty(d: &Difficulty) -> Result<(), (&'static str, String)> {
+    let curves = [
+        ("start_offset", d.start_offset, 0.0),
+        ("step_factor", d.step_factor, 0.05),
+        ("fire_factor", d.fire_factor, 0.05),
+        ("bullet_speed", d.bullet_speed, 1.0),
+        ("max_enemy_shots", d.max_enemy_shots, 1.0),
+    ];
+    for (field, c, min) in curves {
+        if ![c.base, c.per_wave, c.limit].iter().all(|v| v.is_finite()) {
+            return Err((field, "values must be finite numbers".into()));
+        }
+        if c.base < min || c.limit < min {
+            return Err((field, format!("base and limit must be at least {}, got {} and {}", min, c.base, c.limit)));
+        }
+    }
+    Ok(())
+}
+
+fn validate(def: &WaveDef, sprites: &SpriteBank, kinds: &HashMap<String, Rc<AlienKind>>) -> Result<(), (&'static str, String)> {
+    if def.rows.is_empty() {
+        return Err(("rows", "must list at least one row".into()));
+    }
+    let mut widest = 0;
+    let mut bottom = def.start_height;
+    for (r, name) in def.rows.iter().enumerate() {
+        let sprite = kinds.get(name).map(|k| &k.sprite).or_else(|| sprites.get(name)).ok_or_else(|| {
+            let mut names: Vec<&str> = kinds.keys().map(String::as_str).collect();
+            names.sort();
+            names.extend(sprites.names());
+            ("rows", format!("[{}]: no alien kind or sprite named \"{}\" (have {})", r, name, names.join(", ")))
+        })?;
+        let (w, h) = sprite.cells();
+        widest = widest.max(w);
+        bottom += (h + 6) * PIXEL as i32;
+    }
+
+    let cell_w = (widest + 4) * PIXEL as i32;
+    let max_columns = (WINDOW_W - 20) / cell_w;
+    if def.columns == 0 || def.columns as i32 > max_columns {
+        return Err(("columns", format!("must be between 1 and {}, got {}", max_columns, def.columns)));
+    }
+    if def.start_height < 0 {
+        return Err(("start_height", format!("must not be negative, got {}", def.start_height)));
+    }
+    if bottom > SHIELD_Y {
+        return Err(("start_height", format!("puts the last row at y={}, below the shields at y={}", bottom, SHIELD_Y)));
+    }
+    if def.step_interval_ms == 0 {
+        return Err(("step_interval_ms", "must be greater than 0".into()));
+    }
+    if def.fire_interval_ms == 0 {
+        return Err(("fire_interval_ms", "must be greater than 0".into()));
+    }
+    if def.drop <= 0 {
+        return Err(("drop", format!("must be greater than 0, got {}", def.drop)));
+    }
+    if let Some(drops) = &def.drops {
+        validate_drops(drops).map_err(|(field, msg)| ("drops", format!("`{}` {}", field, msg)))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sprites::SPRITES_DIR;
+
+    use super::*;
+
+    const WAVE: &str = "[[wave]]
+rows = [\"alien_1\", \"alien_2\"]
+columns = 8
+start_height = 100
+step_interval_ms = 600
+fire_interval_ms = 900
+drop = 10
+";
+
+    fn sprites() -> SpriteBank {
+        SpriteBank::load_dir(Path::new(SPRITES_DIR)).unwrap()
+    }
+
+    fn error(text: &str) -> String {
+        parse(text, &sprites()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn loads_the_shipped_file() {
+        let waves = load(Path::new(WAVES_PATH), &sprites()).unwrap();
+        assert!(!waves.waves.is_empty());
+    }
+
+    #[test]
+    fn missing_tables_take_defaults() {
+        let waves = parse(WAVE, &sprites()).unwrap();
+        assert_eq!(waves.waves.len(), 1);
+        assert_eq!(waves.waves[0].rows, ["alien_1", "alien_2"]);
+        assert!(waves.kinds.is_empty());
+        assert_eq!(waves.mothership.speed, MothershipDef::default().speed);
+    }
+
+    #[test]
+    fn rows_resolve_alien_kinds() {
+        let wave = WAVE.replace("\"alien_2\"", "\"squid\"");
+        let text = format!("{}\n[aliens.squid]\nsprite = \"alien_1\"\nscore = 30\nhit_points = 2\n", wave);
+        let waves = parse(&text, &sprites()).unwrap();
+        let squid = &waves.kinds["squid"];
+        assert_eq!((squid.score, squid.hit_points), (30, 2));
+    }
+
+    #[test]
+    fn reports_unknown_row_names() {
+        let msg = error(&WAVE.replace("\"alien_2\"", "\"alien_9\""));
+        assert!(msg.starts_with("wave 1: `rows` [1]: no alien kind or sprite named \"alien_9\" (have "), "{}", msg);
+    }
+
+    #[test]
+    fn reports_bad_wave_fields() {
+        let cases = [
+            ("columns = 8", "columns = 0", "wave 1: `columns` must be between 1 and "),
+            ("start_height = 100", "start_height = -5", "wave 1: `start_height` must not be negative, got -5"),
+            ("start_height = 100", "start_height = 440", "wave 1: `start_height` puts the last row at y="),
+            ("step_interval_ms = 600", "step_interval_ms = 0", "wave 1: `step_interval_ms` must be greater than 0"),
+            ("drop = 10", "drop = 0", "wave 1: `drop` must be greater than 0, got 0"),
+        ];
+        for (from, to, expected) in cases {
+            let msg = error(&WAVE.replace(from, to));
+            assert!(msg.starts_with(expected), "{}: {}", to, msg);
+        }
+    }
+
+    #[test]
+    fn numbers_the_wave_at_fault() {
+        let msg = error(&format!("{}{}", WAVE, WAVE.replace("drop = 10", "drop = -1")));
+        assert_eq!(msg, "wave 2: `drop` must be greater than 0, got -1");
+    }
+
+    #[test]
+    fn requires_a_wave() {
+        assert_eq!(error("wave = []"), "file: `wave` at least one [[wave]] is required");
+    }
+
+    #[test]
+    fn reports_bad_tables() {
+        let cases = [
+            ("[aliens.crab]\nscore = 10", "aliens.crab: `sprite` is required"),
+            ("[aliens.crab]\nsprite = \"alien_1\"\nhit_points = 0", "aliens.crab: `hit_points` must be at least 1"),
+            ("[drops]\nchance = 101", "drops: `chance` is a percentage and must be at most 100, got 101"),
+            ("[mothership]\ndelay_ms = [9000, 1000]", "mothership: `delay_ms` must be [shortest, longest], got [9000, 1000]"),
+            ("[mothership]\nspeed = 0", "mothership: `speed` must be between 1 and 20, got 0"),
+            ("[shots.plunger]\nspeed = 0.0", "shots.plunger: `speed` must be greater than 0, got 0"),
+            (
+                "[difficulty.bullet_speed]\nbase = 0.0\nper_wave = 0.0\nlimit = 5.0",
+                "difficulty: `bullet_speed` base and limit must be at least 1, got 0 and 5",
+            ),
+        ];
+        for (table, expected) in cases {
+            assert_eq!(error(&format!("{}\n{}\n", WAVE, table)), expected);
+        }
+    }
+
+    #[test]
+    fn reports_bad_wave_drops() {
+        let msg = error(&format!("{}[wave.drops]\nchance = 200\n", WAVE));
+        assert_eq!(msg, "wave 1: `drops` `chance` is a percentage and must be at most 100, got 200");
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_line() {
+        let msg = error(&WAVE.replace("drop = 10", "drop = 10\nspeed = 3"));
+        assert!(msg.contains("line 8"), "{}", msg);
+        assert!(msg.contains("unknown field `speed`"), "{}", msg);
+    }
+}
@@ -0,0 +1,1564 @@
+use std::{
+    collections::HashMap,
+    rc::Rc,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    clock::ticks_from_ms,
+    powerups::{
+        Capsule,
+        CapsuleSprites,
+        DropTable,
+        Effects,
+        PowerUpKind,
+    },
+    rng::Rng,
+    sprites::{
+        Sprite,
+        SpriteBank,
+        SpriteDef,
+        SpriteError,
+    },
+    waves::{
+        Difficulty,
+        MothershipDef,
+        Shots,
+        WaveDef,
+        Waves,
+    },
+};
+
+pub const PIXEL: u32 = 5;
+pub const WINDOW_W: i32 = 800;
+pub const WINDOW_H: i32 = 600;
+pub const SHIELD_Y: i32 = WINDOW_H - 150;
+pub const DEFAULT_LIVES: u32 = 3;
+/// Extra lives picked up never take the player past this.
+pub const MAX_LIVES: u32 = 9;
+/// Fastest a bullet may travel without skipping cells: its own height.
+pub const MAX_BULLET_SPEED: i32 = PIXEL as i32 * 2;
+
+const ENEMY_FIRE_JITTER_MS: u64 = 300;
+const MOTHERSHIP_MARGIN: i32 = 100;
+/// The arcade's mystery scores, picked by the number of shots fired this
+/// wave: the 8th shot and every 15th after it is worth 300. The famous 23rd
+/// shot is the second of those, the first a mothership is usually around for.
+const MOTHERSHIP_SCORES: [i32; 15] = [100, 50, 50, 100, 150, 100, 100, 50, 300, 100, 100, 100, 50, 150, 100];
+/// Sideways speed of the outer bullets of a triple shot.
+const TRIPLE_SHOT_SPREAD: i32 = 1;
+pub const BUBBLE_COLOR: [u8; 3] = [60, 255, 255];
+/// Ticks each frame of an enemy shot's sprite is shown.
+const SHOT_FRAME_TICKS: u32 = 4;
+
+/// What hitting the mothership with the `shot`th shot of the wave is worth.
+fn mothership_score(shot: u32) -> i32 {
+    MOTHERSHIP_SCORES[shot as usize % MOTHERSHIP_SCORES.len()]
+}
+
+fn sprite_w(sprite: &Sprite) -> i32 {
+    (sprite.first().map(|r| r.len()).unwrap_or(0) as i32) * PIXEL as i32
+}
+
+fn sprite_h(sprite: &Sprite) -> i32 {
+    (sprite.len() as i32) * PIXEL as i32
+}
+
+/// Axis-aligned box used for collisions, kept free of any sdl2 type so the
+/// simulation can run without a window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, w: u32, h: u32) -> Self {
+        Self { x, y, w, h }
+    }
+
+    pub fn has_intersection(&self, other: Rect) -> bool {
+        self.x < other.x + other.w as i32
+            && other.x < self.x + self.w as i32
+            && self.y < other.y + other.h as i32
+            && other.y < self.y + self.h as i32
+    }
+}
+
+/// Presets for `Weapon`, chosen in the options.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FireMode {
+    /// One shot on screen at a time, like the arcade.
+    Classic,
+    Rapid,
+}
+
+impl FireMode {
+    pub const ALL: [FireMode; 2] = [FireMode::Classic, FireMode::Rapid];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FireMode::Classic => "classic",
+            FireMode::Rapid => "rapid",
+        }
+    }
+
+    pub fn weapon(self) -> Weapon {
+        match self {
+            FireMode::Classic => Weapon { max_bullets: 1, cooldown: 0, bullet_speed: 6 },
+            FireMode::Rapid => Weapon { max_bullets: 3, cooldown: ticks_from_ms(250), bullet_speed: 8 },
+        }
+    }
+}
+
+/// How the player's gun fires.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Weapon {
+    /// Player bullets allowed on screen at once.
+    pub max_bullets: u32,
+    /// Ticks that must pass between two shots.
+    pub cooldown: u64,
+    /// Pixels per tick, at most `MAX_BULLET_SPEED`.
+    pub bullet_speed: i32,
+}
+
+/// What the player starts a run with. Replays store it, since it changes how
+/// the same inputs play out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Loadout {
+    pub lives: u32,
+    pub weapon: Weapon,
+}
+
+impl Default for Loadout {
+    fn default() -> Self {
+        Self { lives: DEFAULT_LIVES, weapon: FireMode::Classic.weapon() }
+    }
+}
+
+#[derive(Clone)]
+pub struct Player {
+    pub sprite: Rc<SpriteDef>,
+    pub x: i32,
+    pub y: i32,
+    pub lives: i32,
+    pub weapon: Weapon,
+    pub effects: Effects,
+    last_shot: Option<u64>,
+}
+
+impl Player {
+    pub fn new(sprite: Rc<SpriteDef>, x: i32, y: i32, loadout: Loadout) -> Self {
+        Self {
+            sprite,
+            x,
+            y,
+            lives: loadout.lives as i32,
+            weapon: loadout.weapon,
+            effects: Effects::default(),
+            last_shot: None,
+        }
+    }
+
+    /// The loadout's weapon with any running power-up applied.
+    pub fn current_weapon(&self) -> Weapon {
+        let mut weapon = self.weapon;
+        if self.effects.active(PowerUpKind::RapidFire) {
+            weapon.max_bullets += 2;
+            weapon.cooldown /= 2;
+            weapon.bullet_speed = (weapon.bullet_speed + 2).min(MAX_BULLET_SPEED);
+        }
+        weapon
+    }
+
+    /// Bullets one press of fire puts on screen.
+    fn shots_per_volley(&self) -> usize {
+        if self.effects.active(PowerUpKind::TripleShot) { 3 } else { 1 }
+    }
+
+    /// Whether a shot fired at `tick` is allowed with `in_flight` of the
+    /// player's bullets still on screen. A volley counts as one shot until
+    /// all of its bullets are gone.
+    fn can_fire(&self, tick: u64, in_flight: usize) -> bool {
+        let weapon = self.current_weapon();
+        in_flight.div_ceil(self.shots_per_volley()) < weapon.max_bullets as usize
+            && self.last_shot.is_none_or(|t| tick - t >= weapon.cooldown)
+    }
+
+    pub fn w(&self) -> i32 {
+        sprite_w(&self.sprite.frames[0])
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.w() as u32, sprite_h(&self.sprite.frames[0]) as u32)
+    }
+
+    /// Area a shield bubble covers, a little larger than the ship.
+    pub fn bubble(&self) -> Rect {
+        let r = self.rect();
+        let margin = PIXEL as i32 * 2;
+        Rect::new(r.x - margin, r.y - margin, r.w + margin as u32 * 2, r.h + margin as u32 * 2)
+    }
+}
+
+// Cells around an impact that may also be knocked out, each with even odds.
+const BLAST: [(i32, i32); 8] = [
+    (-1, -1), (-1, 0), (-1, 1),
+    (0, -1),           (0, 1),
+    (1, -1),  (1, 0),  (1, 1),
+];
+
+/// A bunker whose `sprite` is its actual state: hits clear cells out of the
+/// grid and it is gone once no cell is left.
+#[derive(Clone)]
+pub struct Shield {
+    pub sprite: Sprite,
+    pub color: [u8; 3],
+    pub x: i32,
+    pub y: i32,
+    pub alive: bool,
+}
+
+impl Shield {
+    pub fn new(def: &SpriteDef, x: i32, y: i32) -> Self {
+        Self { sprite: def.frames[0].clone(), color: def.color, x, y, alive: true }
+    }
+
+    /// Grid cells (row, col) that `rect` covers, clamped to the sprite.
+    fn cells_under(&self, rect: Rect) -> impl Iterator<Item = (usize, usize)> + use<> {
+        let p = PIXEL as i32;
+        let rows = self.sprite.len() as i32;
+        let cols = self.sprite.first().map(|r| r.len()).unwrap_or(0) as i32;
+        let r0 = ((rect.y - self.y) / p).max(0);
+        let r1 = ((rect.y + rect.h as i32 - 1 - self.y).div_euclid(p)).min(rows - 1);
+        let c0 = ((rect.x - self.x) / p).max(0);
+        let c1 = ((rect.x + rect.w as i32 - 1 - self.x).div_euclid(p)).min(cols - 1);
+        (r0..=r1).flat_map(move |r| (c0..=c1).map(move |c| (r as usize, c as usize)))
+    }
+
+    /// First solid cell `rect` touches, seen from the side the shot comes
+    /// from so a bullet bites into the near face rather than the far one.
+    fn hit_cell(&self, rect: Rect, from_below: bool) -> Option<(usize, usize)> {
+        if !self.alive || !rect.has_intersection(self.rect()) {
+            return None;
+        }
+        let mut hits = self.cells_under(rect).filter(|&(r, c)| self.sprite[r][c] == 1);
+        if from_below { hits.last() } else { hits.next() }
+    }
+
+    fn clear(&mut self, row: i32, col: i32) {
+        if let Some(cell) = self.sprite.get_mut(row as usize).and_then(|r| r.get_mut(col as usize)) {
+            *cell = 0;
+        }
+    }
+
+    fn update_alive(&mut self) {
+        self.alive = self.sprite.iter().flatten().any(|&p| p == 1);
+    }
+
+    /// Knocks out the hit cell and a random handful of its neighbours.
+    fn blast(&mut self, (row, col): (usize, usize), rng: &mut Rng) {
+        let (row, col) = (row as i32, col as i32);
+        self.clear(row, col);
+        for (dr, dc) in BLAST {
+            if rng.chance(1, 2) {
+                self.clear(row + dr, col + dc);
+            }
+        }
+        self.update_alive();
+    }
+
+    /// Wipes every cell under `rect`, used for aliens ploughing through.
+    fn erode(&mut self, rect: Rect) {
+        if !self.alive || !rect.has_intersection(self.rect()) {
+            return;
+        }
+        for (r, c) in self.cells_under(rect).collect::<Vec<_>>() {
+            self.sprite[r][c] = 0;
+        }
+        self.update_alive();
+    }
+
+    pub fn w(&self) -> i32 {
+        sprite_w(&self.sprite)
+    }
+
+    pub fn h(&self) -> i32 {
+        sprite_h(&self.sprite)
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.w() as u32, self.h() as u32)
+    }
+}
+
+/// Which projectile an alien fires.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShotKind {
+    #[default]
+    Rolling,
+    /// Fired from the column nearest above the player.
+    Plunger,
+    Squiggly,
+}
+
+impl ShotKind {
+    pub const ALL: [ShotKind; 3] = [ShotKind::Rolling, ShotKind::Plunger, ShotKind::Squiggly];
+
+    /// Key in the waves file; the sprite is `shot_<name>`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ShotKind::Rolling => "rolling",
+            ShotKind::Plunger => "plunger",
+            ShotKind::Squiggly => "squiggly",
+        }
+    }
+}
+
+/// What happens when a player shot meets an enemy shot.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShotCollision {
+    /// Both shots are destroyed.
+    #[default]
+    Cancel,
+    /// The enemy shot destroys the player's and keeps going.
+    Pierce,
+}
+
+/// What an alien is worth and how it fights. Rows in the waves file name
+/// either a kind from its `[aliens]` table or a bare sprite, which gets the
+/// `AlienKind::plain` stats.
+#[derive(Debug)]
+pub struct AlienKind {
+    pub sprite: Rc<SpriteDef>,
+    pub score: i32,
+    pub hit_points: u32,
+    /// Odds of being picked as the next shooter, relative to the other
+    /// aliens that could fire. 0 never fires.
+    pub fire_rate: f32,
+    pub bullet: ShotKind,
+}
+
+impl AlienKind {
+    pub const DEFAULT_SCORE: i32 = 20;
+
+    pub fn plain(sprite: Rc<SpriteDef>) -> Self {
+        Self {
+            sprite,
+            score: Self::DEFAULT_SCORE,
+            hit_points: 1,
+            fire_rate: 1.0,
+            bullet: ShotKind::Rolling,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Alien {
+    kind: Rc<AlienKind>,
+    frame_ix: usize,
+    frame_interval: u64,
+    last_frame: u64,
+
+    pub x: i32,
+    pub y: i32,
+    /// Hits left before it dies.
+    pub hit_points: u32,
+    pub alive: bool,
+}
+
+impl Alien {
+    pub fn new(kind: Rc<AlienKind>, x: i32, y: i32) -> Self {
+        Self {
+            frame_ix: 0,
+            frame_interval: ticks_from_ms(800),
+            last_frame: 0,
+            x,
+            y,
+            hit_points: kind.hit_points,
+            alive: true,
+            kind,
+        }
+    }
+
+    pub fn current_sprite(&self) -> &Sprite {
+        &self.kind.sprite.frames[self.frame_ix]
+    }
+
+    pub fn sprite(&self) -> &SpriteDef {
+        &self.kind.sprite
+    }
+
+    /// Takes one hit and reports whether it killed the alien.
+    fn hit(&mut self) -> bool {
+        self.hit_points = self.hit_points.saturating_sub(1);
+        self.alive = self.hit_points > 0;
+        !self.alive
+    }
+
+    pub fn frame_ix(&self) -> usize {
+        self.frame_ix
+    }
+
+    fn update_animation(&mut self, tick: u64) {
+        if tick - self.last_frame >= self.frame_interval {
+            self.frame_ix = (self.frame_ix + 1) % self.kind.sprite.frames.len();
+            self.last_frame = tick;
+        }
+    }
+
+    pub fn w(&self) -> i32 {
+        sprite_w(self.current_sprite())
+    }
+
+    pub fn h(&self) -> i32 {
+        sprite_h(self.current_sprite())
+    }
+
+    fn translate(&mut self, dx: i32, dy: i32) {
+        self.x += dx;
+        self.y += dy;
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.w() as u32, self.h() as u32)
+    }
+}
+
+fn spawner_grid(
+    origin: (i32, i32),
+    rows: u32,
+    cols: u32,
+    kind: Rc<AlienKind>,
+) -> Vec<Alien> {
+    let (ox, oy) = origin;
+
+    let (sprite_w, sprite_h) = kind.sprite.cells();
+
+    let cell_w = (sprite_w + 4) * PIXEL as i32;
+    let cell_h = (sprite_h + 6) * PIXEL as i32;
+
+    (0..rows)
+        .flat_map(|r| {
+            let f = kind.clone();
+            (0..cols).map(move |c| {
+                Alien::new(
+                    f.clone(),
+                    ox + c as i32 * cell_w,
+                    oy + r as i32 * cell_h,
+                )
+            })
+        })
+        .collect()
+}
+
+fn fleet_manager(aliens: &[Alien]) -> Option<(i32, i32, i32)> {
+    let mut min_x = 0;
+    let mut max_x = 0;
+    let mut max_y = 0;
+    let mut first = true;
+
+    for a in aliens.iter().filter(|a| a.alive) {
+        let (x, y, w, h) = (a.x, a.y, a.w(), a.h());
+        if first {
+            min_x = x;
+            max_x = x + w;
+            max_y = y + h;
+            first = false;
+        } else {
+            if x < min_x { min_x = x; }
+            if x + w > max_x { max_x = x + w; }
+            if y + h > max_y { max_y = y + h; }
+        }
+    }
+    if first { None } else { Some((min_x, max_x, max_y)) }
+}
+
+/// The kind a wave row names: one from the `[aliens]` table, or else a
+/// plain alien drawn with the sprite of that name.
+fn row_kind(sprites: &SpriteBank, kinds: &HashMap<String, Rc<AlienKind>>, name: &str) -> Rc<AlienKind> {
+    kinds.get(name).cloned().unwrap_or_else(|| {
+        Rc::new(AlienKind::plain(sprites.get(name).expect("wave validated against sprites").clone()))
+    })
+}
+
+/// Lays out a wave's rows under each other, each centred on its own so
+/// rows of narrower aliens sit under the middle of wider ones. The wave is
+/// expected to have gone through `waves::load` validation.
+fn wave(sprites: &SpriteBank, kinds: &HashMap<String, Rc<AlienKind>>, def: &WaveDef) -> Vec<Alien> {
+    let mut aliens = Vec::new();
+
+    let mut y = def.start_height;
+    for name in &def.rows {
+        let kind = row_kind(sprites, kinds, name);
+        let (w, h) = kind.sprite.cells();
+        let origin_x = (WINDOW_W - def.columns as i32 * (w + 4) * PIXEL as i32) / 2;
+        aliens.extend(spawner_grid((origin_x, y), 1, def.columns, kind));
+        y += (h + 6) * PIXEL as i32;
+    }
+    aliens
+}
+
+pub struct Bullet {
+    pub x: i32,
+    pub y: i32,
+    vx: i32,
+    vy: i32,
+    pub w: u32,
+    pub h: u32,
+    /// Enemy shots are drawn with their type's sprite; the player's are
+    /// plain rectangles.
+    sprite: Option<Rc<SpriteDef>>,
+    age: u32,
+    /// For enemy shots, what a player shot meeting it does.
+    collision: ShotCollision,
+    /// Keeps going after killing an alien.
+    pub pierce: bool,
+    pub alive: bool,
+}
+
+impl Bullet {
+    fn new(x: i32, y: i32, vy: i32) -> Self {
+        Self {
+            x,
+            y,
+            vx: 0,
+            vy,
+            w: PIXEL,
+            h: PIXEL * 2,
+            sprite: None,
+            age: 0,
+            collision: ShotCollision::Cancel,
+            pierce: false,
+            alive: true,
+        }
+    }
+
+    /// An enemy shot drawn with `sprite`, centred on `center_x` with its top
+    /// at `y`.
+    fn shot(sprite: Rc<SpriteDef>, collision: ShotCollision, center_x: i32, y: i32, vy: i32) -> Self {
+        let (w, h) = (sprite_w(&sprite.frames[0]), sprite_h(&sprite.frames[0]));
+        Self {
+            x: center_x - w / 2,
+            w: w as u32,
+            h: h as u32,
+            sprite: Some(sprite),
+            collision,
+            ..Self::new(0, y, vy)
+        }
+    }
+
+    pub fn sprite(&self) -> Option<&SpriteDef> {
+        self.sprite.as_deref()
+    }
+
+    pub fn frame_ix(&self) -> usize {
+        let frames = self.sprite.as_ref().map_or(1, |s| s.frames.len());
+        (self.age / SHOT_FRAME_TICKS) as usize % frames
+    }
+
+    fn update(&mut self) {
+        self.age += 1;
+        self.x += self.vx;
+        self.y += self.vy;
+        if self.y + self.h as i32 <= 0 || self.y >= WINDOW_H || self.x + self.w as i32 <= 0 || self.x >= WINDOW_W {
+            self.alive = false;
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.w, self.h)
+    }
+}
+
+fn overlap_x(a: &Alien, b: &Alien) -> bool {
+    let ax0 = a.x;
+    let ax1 = a.x + a.w();
+    let bx0 = b.x;
+    let bx1 = b.x + b.w();
+    ax0 < bx1 && bx0 < ax1
+}
+
+fn is_bottommost(a: &Alien, aliens: &[Alien]) -> bool {
+    if !a.alive { return false; }
+    !aliens.iter().any(|other| other.alive && overlap_x(a, other) && other.y > a.y)
+}
+
+fn bottom_shooters(aliens: &[Alien]) -> Vec<usize> {
+    aliens.iter().enumerate()
+        .filter(|(_, a)| is_bottommost(a, aliens))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Player intent for a single step, sampled by whoever drives the world.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Input {
+    pub left: bool,
+    pub right: bool,
+    pub fire: bool,
+}
+
+/// Centre of a hit and the colour of whatever took it, for effects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Impact {
+    pub x: i32,
+    pub y: i32,
+    pub color: [u8; 3],
+}
+
+impl Impact {
+    fn on(rect: Rect, color: [u8; 3]) -> Self {
+        Self { x: rect.x + rect.w as i32 / 2, y: rect.y + rect.h as i32 / 2, color }
+    }
+}
+
+/// Things that happened during a step, for the frontend to react to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameEvent {
+    /// The fleet took one step; drives the march, so it follows `step_interval`.
+    FleetStepped,
+    PlayerFired,
+    PowerUpCollected(PowerUpKind),
+    /// An alien took a hit it survived.
+    AlienHit(Impact),
+    /// With the points it was worth.
+    AlienKilled(Impact, i32),
+    MothershipKilled(Impact, i32),
+    /// A bunker or the shield bubble stopped a shot.
+    ShieldHit(Impact),
+    /// A player shot and an enemy shot met.
+    ShotsCollided(Impact),
+    PlayerHit(Impact),
+    WaveCleared,
+    GameOver(LossReason),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LossReason {
+    OutOfLives,
+    Invaded,
+}
+
+pub type Events = Vec<GameEvent>;
+
+/// Difficulty in effect for the current wave, resolved from the curves in
+/// the waves file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WaveStats {
+    pub start_offset: i32,
+    pub step_factor: f32,
+    pub fire_factor: f32,
+    pub bullet_speed: i32,
+    pub max_enemy_shots: usize,
+}
+
+impl WaveStats {
+    fn for_wave(d: &Difficulty, wave_level: u32) -> Self {
+        Self {
+            start_offset: d.start_offset.at(wave_level).round() as i32,
+            step_factor: d.step_factor.at(wave_level),
+            fire_factor: d.fire_factor.at(wave_level),
+            bullet_speed: d.bullet_speed.at(wave_level).round() as i32,
+            max_enemy_shots: d.max_enemy_shots.at(wave_level) as usize,
+        }
+    }
+}
+
+/// The whole game simulation: every rule of a run lives here and none of it
+/// touches sdl2, so it can be stepped from tests, bots or a server.
+pub struct World {
+    sprites: SpriteBank,
+    shield_sprite: Rc<SpriteDef>,
+    mothership_kind: Rc<AlienKind>,
+    mothership_def: MothershipDef,
+    kinds: HashMap<String, Rc<AlienKind>>,
+    capsule_sprites: CapsuleSprites,
+    shot_sprites: Vec<Rc<SpriteDef>>,
+    shots: Shots,
+    waves: Vec<WaveDef>,
+    difficulty: Difficulty,
+    default_drops: DropTable,
+    drops: DropTable,
+
+    pub player: Player,
+    pub aliens: Vec<Alien>,
+    pub shields: Vec<Shield>,
+    pub mothership: Alien,
+    pub player_bullets: Vec<Bullet>,
+    pub enemy_bullets: Vec<Bullet>,
+    pub capsules: Vec<Capsule>,
+
+    pub score: i32,
+    pub wave_level: u32,
+    pub stats: WaveStats,
+
+    seed: u64,
+    rng: Rng,
+
+    // All timers are in simulation ticks, see `clock::TICK_RATE`.
+    tick: u64,
+    direction: i32,
+    drop: i32,
+    step_timer: u64,
+    step_interval: u64,
+    base_step_interval: u64,
+    enemy_fire_timer: u64,
+    enemy_fire_interval: u64,
+    enemy_fire_delay: u64,
+    last_trip: u64,
+    mothership_cd: u64,
+    mothership_dir: i32,
+    /// Player volleys this wave, for the mystery score.
+    shots_fired: u32,
+}
+
+impl World {
+    /// `waves` must come from `waves::load` against the same `sprites`.
+    /// Fails if one of the sprites the game itself needs is missing.
+    pub fn new(sprites: SpriteBank, waves: Waves, seed: u64, loadout: Loadout) -> Result<Self, SpriteError> {
+        let player = Player::new(sprites.require("spaceship")?.clone(), WINDOW_W / 2, WINDOW_H - 50, loadout);
+        let shield_sprite = sprites.require("shield")?.clone();
+        let mothership_kind = Rc::new(AlienKind::plain(sprites.require("mothership")?.clone()));
+        let mothership = Alien::new(mothership_kind.clone(), -MOTHERSHIP_MARGIN, 20);
+        let capsule_sprites = CapsuleSprites::load(&sprites)?;
+        // In `ShotKind::ALL` order, so a kind's discriminant indexes it.
+        let shot_sprites = ShotKind::ALL.iter()
+            .map(|k| sprites.require(&format!("shot_{}", k.name())).cloned())
+            .collect::<Result<_, _>>()?;
+        let mut world = Self {
+            shield_sprite,
+            mothership_kind,
+            mothership_def: waves.mothership,
+            kinds: waves.kinds,
+            capsule_sprites,
+            shot_sprites,
+            shots: waves.shots,
+            stats: WaveStats::for_wave(&waves.difficulty, 1),
+            sprites,
+            waves: waves.waves,
+            difficulty: waves.difficulty,
+            drops: waves.drops.clone(),
+            default_drops: waves.drops,
+            player,
+            aliens: Vec::new(),
+            shields: Vec::new(),
+            mothership,
+            player_bullets: Vec::new(),
+            enemy_bullets: Vec::new(),
+            capsules: Vec::new(),
+            score: 0,
+            wave_level: 1,
+            seed,
+            rng: Rng::new(seed),
+            tick: 0,
+            direction: 1,
+            drop: 0,
+            step_timer: 0,
+            step_interval: 0,
+            base_step_interval: 0,
+            enemy_fire_timer: 0,
+            enemy_fire_interval: ticks_from_ms(900),
+            enemy_fire_delay: ticks_from_ms(900),
+            last_trip: 0,
+            mothership_cd: ticks_from_ms(5000),
+            mothership_dir: 1,
+            shots_fired: 0,
+        };
+        world.reset(seed, loadout);
+        Ok(world)
+    }
+
+    /// Ticks simulated since the run started.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Seed the current run was started with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Starts a fresh run; the same seed, loadout and inputs replay the
+    /// same game.
+    pub fn reset(&mut self, seed: u64, loadout: Loadout) {
+        self.seed = seed;
+        self.rng = Rng::new(seed);
+        self.tick = 0;
+        self.score = 0;
+        self.wave_level = 1;
+        self.start_wave();
+
+        self.mothership = Alien::new(self.mothership_kind.clone(), -MOTHERSHIP_MARGIN, 20);
+        self.schedule_mothership(self.mothership_def.first_delay_ms);
+
+        self.enemy_fire_timer = 0;
+        self.enemy_fire_delay = self.enemy_fire_interval;
+
+        self.player.x = WINDOW_W / 2;
+        self.player.lives = loadout.lives as i32;
+        self.player.weapon = loadout.weapon;
+        self.player.effects.clear();
+        self.player.last_shot = None;
+
+        self.build_shields();
+    }
+
+    /// Puts up all three bunkers undamaged.
+    fn build_shields(&mut self) {
+        self.shields.clear();
+        let shield_w = self.shield_sprite.cells().0 * PIXEL as i32;
+        let shield_gap = WINDOW_W / 4;
+        for n in 1..=3 {
+            self.shields.push(Shield::new(&self.shield_sprite, n * shield_gap - shield_w / 2, SHIELD_Y));
+        }
+    }
+
+    /// Spawns the fleet for `wave_level` and takes its pacing from the
+    /// wave definition scaled by the difficulty curve. The fleet holds still
+    /// for two steps before moving, and no shot of the last wave carries over.
+    fn start_wave(&mut self) {
+        let def = &self.waves[(self.wave_level as usize - 1) % self.waves.len()];
+        self.stats = WaveStats::for_wave(&self.difficulty, self.wave_level);
+
+        self.drops = def.drops.clone().unwrap_or_else(|| self.default_drops.clone());
+        self.shots_fired = 0;
+        self.capsules.clear();
+        self.player_bullets.clear();
+        self.enemy_bullets.clear();
+
+        self.aliens = wave(&self.sprites, &self.kinds, def);
+        let bottom = fleet_manager(&self.aliens).map(|(_, _, max_y)| max_y).unwrap_or(0);
+        let offset = self.stats.start_offset.min(SHIELD_Y - bottom).max(0);
+        for a in self.aliens.iter_mut() {
+            a.translate(0, offset);
+        }
+
+        self.direction = 1;
+        self.drop = def.drop;
+        self.base_step_interval = (ticks_from_ms(def.step_interval_ms) as f32 * self.stats.step_factor) as u64;
+        self.step_interval = self.base_step_interval * 2;
+        self.step_timer = self.tick;
+        self.enemy_fire_interval = (ticks_from_ms(def.fire_interval_ms) as f32 * self.stats.fire_factor) as u64;
+    }
+
+    /// Advances the simulation by exactly one tick.
+    pub fn step(&mut self, input: Input) -> Events {
+        let mut events = Events::new();
+        self.tick += 1;
+
+        for a in self.aliens.iter_mut() {
+            a.update_animation(self.tick);
+        }
+
+        self.player.effects.expire(self.tick);
+        self.move_player(input, &mut events);
+        self.update_capsules(&mut events);
+        self.update_mothership();
+        self.step_fleet(&mut events);
+        self.update_player_bullets(&mut events);
+        self.enemy_fire();
+        self.update_enemy_bullets(&mut events);
+        self.collide_bullets(&mut events);
+        self.shields.retain(|s| s.alive);
+
+        if self.aliens.iter().all(|a| !a.alive) {
+            self.wave_level += 1;
+            self.start_wave();
+            events.push(GameEvent::WaveCleared);
+        }
+
+        events
+    }
+
+    fn move_player(&mut self, input: Input, events: &mut Events) {
+        let player_w = self.player.w();
+
+        if input.left {
+            self.player.x -= 5;
+            self.player.x = self.player.x.max(0);
+        }
+
+        if input.right {
+            self.player.x += 5;
+            self.player.x = self.player.x.min(WINDOW_W - player_w);
+        }
+
+        if input.fire && self.player.can_fire(self.tick, self.player_bullets.len()) {
+            let tip_x = self.player.x + (player_w / 2) - (PIXEL as i32 / 2);
+            let tip_y = self.player.y - PIXEL as i32 * 2;
+            let speed = self.player.current_weapon().bullet_speed;
+            let pierce = self.player.effects.active(PowerUpKind::PiercingLaser);
+            let spread: &[i32] = if self.player.shots_per_volley() == 3 {
+                &[-TRIPLE_SHOT_SPREAD, 0, TRIPLE_SHOT_SPREAD]
+            } else {
+                &[0]
+            };
+            for &vx in spread {
+                self.player_bullets.push(Bullet { vx, pierce, ..Bullet::new(tip_x, tip_y, -speed) });
+            }
+            self.player.last_shot = Some(self.tick);
+            self.shots_fired += 1;
+            events.push(GameEvent::PlayerFired);
+        }
+    }
+
+    /// Rolls the wave's drop table for a kill centred on `center_x`.
+    fn maybe_drop(&mut self, center_x: i32, y: i32, mothership: bool) {
+        if let Some(kind) = self.drops.roll(&mut self.rng, mothership) {
+            self.capsules.push(self.capsule_sprites.spawn(kind, center_x, y));
+        }
+    }
+
+    fn update_capsules(&mut self, events: &mut Events) {
+        let player = self.player.rect();
+        let mut collected = Vec::new();
+        for c in self.capsules.iter_mut() {
+            c.update();
+            if c.alive && c.rect().has_intersection(player) {
+                c.alive = false;
+                collected.push(c.kind);
+            }
+        }
+        self.capsules.retain(|c| c.alive);
+
+        for kind in collected {
+            self.collect(kind);
+            events.push(GameEvent::PowerUpCollected(kind));
+        }
+    }
+
+    fn collect(&mut self, kind: PowerUpKind) {
+        match kind {
+            PowerUpKind::ExtraLife => self.player.lives = (self.player.lives + 1).min(MAX_LIVES as i32),
+            PowerUpKind::ShieldRepair => self.build_shields(),
+            _ => {
+                let ticks = kind.duration().expect("every other kind is timed");
+                self.player.effects.start(kind, self.tick, ticks);
+            }
+        }
+    }
+
+    /// Grounds the mothership and picks the wait before its next trip.
+    fn schedule_mothership(&mut self, [lo, hi]: [u64; 2]) {
+        self.last_trip = self.tick;
+        self.mothership_cd = self.rng.range(ticks_from_ms(lo), ticks_from_ms(hi));
+        self.mothership.alive = false;
+    }
+
+    /// The mothership is only `alive` while it is crossing. Once the wait is
+    /// over it sets off from a random side, unless the fleet is too thin, in
+    /// which case the wait starts over.
+    fn update_mothership(&mut self) {
+        if !self.mothership.alive {
+            if self.tick - self.last_trip < self.mothership_cd {
+                return;
+            }
+            let left = self.aliens.iter().filter(|a| a.alive).count();
+            if left < self.mothership_def.min_aliens as usize {
+                self.schedule_mothership(self.mothership_def.delay_ms);
+                return;
+            }
+            self.mothership_dir = if self.rng.chance(1, 2) { 1 } else { -1 };
+            self.mothership.x = if self.mothership_dir > 0 {
+                -MOTHERSHIP_MARGIN
+            } else {
+                WINDOW_W + MOTHERSHIP_MARGIN - self.mothership.w()
+            };
+            self.mothership.alive = true;
+        }
+
+        let m = &self.mothership;
+        let in_flight = if self.mothership_dir > 0 {
+            m.x <= WINDOW_W + m.w()
+        } else {
+            m.x + m.w() >= -m.w()
+        };
+
+        if in_flight {
+            self.mothership.translate(self.mothership_def.speed * self.mothership_dir, 0);
+        } else {
+            self.schedule_mothership(self.mothership_def.delay_ms);
+        }
+    }
+
+    fn step_fleet(&mut self, events: &mut Events) {
+        if self.tick - self.step_timer < self.step_interval {
+            return;
+        }
+
+        let step = PIXEL as i32;
+        let mut descend = false;
+
+        if let Some((min_x, max_x, _max_y)) = fleet_manager(&self.aliens) {
+            let left_limit = 10;
+            let right_limit = WINDOW_W - 10;
+
+            if self.direction > 0 && max_x + step >= right_limit {
+                self.direction = -1;
+                descend = true;
+            }
+            if self.direction < 0 && min_x - step <= left_limit {
+                self.direction = 1;
+                descend = true;
+            }
+        }
+
+        let dx = self.direction * step;
+        let dy = if descend { self.drop } else { 0 };
+
+        for a in self.aliens.iter_mut().filter(|a| a.alive) {
+            a.translate(if descend { 0 } else { dx }, dy);
+        }
+        events.push(GameEvent::FleetStepped);
+
+        self.check_invasion(events);
+
+        self.step_timer = self.tick;
+        let total_aliens = self.aliens.len();
+        let alive_aliens = self.aliens.iter().filter(|a| a.alive).count().max(1);
+        let ratio = alive_aliens as f32 / total_aliens as f32; // 1.0 .. 0.0
+        self.step_interval = (self.base_step_interval as f32 * (1.0 + 2.0 * ratio) / 3.0) as u64;
+    }
+
+    /// Aliens eat through any shield they reach, and once the lowest one gets
+    /// down to the player's row the game is over regardless of lives left.
+    fn check_invasion(&mut self, events: &mut Events) {
+        for a in self.aliens.iter().filter(|a| a.alive) {
+            for s in self.shields.iter_mut() {
+                s.erode(a.rect());
+            }
+        }
+
+        if let Some((_, _, max_y)) = fleet_manager(&self.aliens)
+            && max_y >= self.player.y
+        {
+            self.player.lives = 0;
+            events.push(GameEvent::GameOver(LossReason::Invaded));
+        }
+    }
+
+    fn update_player_bullets(&mut self, events: &mut Events) {
+        // Drops are rolled once the bullets are done with `self`.
+        let mut kills = Vec::new();
+        for b in self.player_bullets.iter_mut() {
+            b.update();
+
+            if !b.alive { continue; }
+
+            for s in self.shields.iter_mut() {
+                if let Some(cell) = s.hit_cell(b.rect(), true) {
+                    s.blast(cell, &mut self.rng);
+                    b.alive = false;
+                    events.push(GameEvent::ShieldHit(Impact::on(b.rect(), s.color)));
+                    break;
+                }
+            }
+
+            if self.mothership.alive && b.rect().has_intersection(self.mothership.rect()) {
+                let m = &self.mothership;
+                let value = mothership_score(self.shots_fired);
+                kills.push((m.x + m.w() / 2, m.y + m.h(), true));
+                events.push(GameEvent::MothershipKilled(Impact::on(m.rect(), m.sprite().color), value));
+                self.mothership.alive = false;
+                b.alive = false;
+                self.score += value;
+            }
+
+            for a in &mut self.aliens {
+                if !a.alive || !b.alive { continue; }
+                if b.rect().has_intersection(a.rect()) {
+                    if a.hit() {
+                        b.alive = b.pierce;
+                        kills.push((a.x + a.w() / 2, a.y + a.h(), false));
+                        self.score += a.kind.score;
+                        events.push(GameEvent::AlienKilled(Impact::on(a.rect(), a.sprite().color), a.kind.score));
+                    } else {
+                        b.alive = false;
+                        events.push(GameEvent::AlienHit(Impact::on(a.rect(), a.sprite().color)));
+                    }
+                }
+            }
+        }
+
+        self.player_bullets.retain(|b| b.alive);
+        for (x, y, mothership) in kills {
+            if mothership {
+                self.schedule_mothership(self.mothership_def.delay_ms);
+            }
+            self.maybe_drop(x, y, mothership);
+        }
+    }
+
+    fn enemy_fire(&mut self) {
+        if self.tick - self.enemy_fire_timer < self.enemy_fire_delay {
+            return;
+        }
+
+        if self.enemy_bullets.len() >= self.stats.max_enemy_shots {
+            return;
+        }
+
+        let shooters = bottom_shooters(&self.aliens);
+        let weights: Vec<u64> = shooters.iter()
+            .map(|&i| (self.aliens[i].kind.fire_rate * 100.0).round() as u64)
+            .collect();
+        if let Some(n) = self.rng.weighted(&weights) {
+            let kind = self.aliens[shooters[n]].kind.bullet;
+            let idx = if kind == ShotKind::Plunger {
+                self.shooter_above_player(&shooters).unwrap_or(shooters[n])
+            } else {
+                shooters[n]
+            };
+            let a = &self.aliens[idx];
+            let sprite = self.shot_sprites[kind as usize].clone();
+            let max_speed = sprite_h(&sprite.frames[0]);
+            let def = self.shots.get(kind);
+            let speed = (self.stats.bullet_speed as f32 * def.speed).round() as i32;
+            let shot = Bullet::shot(sprite, def.collision, a.x + a.w() / 2, a.y + a.h(), speed.clamp(1, max_speed));
+            self.enemy_bullets.push(shot);
+        }
+        self.enemy_fire_timer = self.tick;
+        let jitter = ticks_from_ms(ENEMY_FIRE_JITTER_MS);
+        self.enemy_fire_delay = self.rng.range(
+            self.enemy_fire_interval.saturating_sub(jitter),
+            self.enemy_fire_interval + jitter,
+        );
+    }
+
+    /// Player and enemy shots that meet. Both are destroyed unless the enemy
+    /// shot's type pierces or the player's shot is a piercing laser, which
+    /// then goes on; when both would go on they pass through each other.
+    fn collide_bullets(&mut self, events: &mut Events) {
+        for eb in self.enemy_bullets.iter_mut().filter(|b| b.alive) {
+            for pb in self.player_bullets.iter_mut().filter(|b| b.alive) {
+                let enemy_pierces = eb.collision == ShotCollision::Pierce;
+                if (pb.pierce && enemy_pierces) || !pb.rect().has_intersection(eb.rect()) {
+                    continue;
+                }
+                pb.alive = pb.pierce;
+                eb.alive = enemy_pierces;
+                let at = Impact { x: pb.x + pb.w as i32 / 2, y: pb.y, color: [255, 255, 255] };
+                events.push(GameEvent::ShotsCollided(at));
+                if !eb.alive {
+                    break;
+                }
+            }
+        }
+        self.player_bullets.retain(|b| b.alive);
+        self.enemy_bullets.retain(|b| b.alive);
+    }
+
+    /// The shooter whose column the player is under, or else the one whose
+    /// centre is closest to the player's, whatever kind of alien it is as
+    /// long as it fires at all.
+    fn shooter_above_player(&self, shooters: &[usize]) -> Option<usize> {
+        let (x0, x1) = (self.player.x, self.player.x + self.player.w());
+        let distance = |&i: &usize| (self.aliens[i].x + self.aliens[i].w() / 2 - (x0 + x1) / 2).abs();
+        let firing = || shooters.iter().copied().filter(|&i| self.aliens[i].kind.fire_rate > 0.0);
+        firing()
+            .filter(|&i| self.aliens[i].x < x1 && x0 < self.aliens[i].x + self.aliens[i].w())
+            .min_by_key(distance)
+            .or_else(|| firing().min_by_key(distance))
+    }
+
+    fn update_enemy_bullets(&mut self, events: &mut Events) {
+        for eb in self.enemy_bullets.iter_mut() {
+            eb.update();
+
+            if eb.alive {
+                for s in self.shields.iter_mut() {
+                    if let Some(cell) = s.hit_cell(eb.rect(), false) {
+                        s.blast(cell, &mut self.rng);
+                        eb.alive = false;
+                        events.push(GameEvent::ShieldHit(Impact::on(eb.rect(), s.color)));
+                        break;
+                    }
+                }
+            }
+
+            if eb.alive && self.player.effects.active(PowerUpKind::ShieldBubble)
+                && eb.rect().has_intersection(self.player.bubble())
+            {
+                eb.alive = false;
+                events.push(GameEvent::ShieldHit(Impact::on(eb.rect(), BUBBLE_COLOR)));
+                continue;
+            }
+
+            if eb.rect().has_intersection(self.player.rect()) {
+                self.player.lives -= 1;
+                eb.alive = false;
+                events.push(GameEvent::PlayerHit(Impact::on(self.player.rect(), self.player.sprite.color)));
+                if self.player.lives < 1 {
+                    events.push(GameEvent::GameOver(LossReason::OutOfLives));
+                }
+                break;
+            }
+        }
+
+        self.enemy_bullets.retain(|b| b.alive);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{
+        sprites::SPRITES_DIR,
+        waves::{
+            self,
+            WAVES_PATH,
+        },
+    };
+
+    fn world(seed: u64) -> World {
+        let sprites = SpriteBank::load_dir(Path::new(SPRITES_DIR)).unwrap();
+        let waves = waves::load(Path::new(WAVES_PATH), &sprites).unwrap();
+        World::new(sprites, waves, seed, Loadout::default()).unwrap()
+    }
+
+    /// Sweeps left and right while holding fire, so bullets, kills and
+    /// drops all get exercised.
+    fn input_at(tick: u64) -> Input {
+        let right = (tick / 90).is_multiple_of(2);
+        Input { left: !right, right, fire: true }
+    }
+
+    fn run(world: &mut World, ticks: u64) -> Events {
+        (0..ticks).flat_map(|t| world.step(input_at(t))).collect()
+    }
+
+    #[test]
+    fn step_advances_one_tick() {
+        let mut w = world(1);
+        w.step(Input::default());
+        w.step(Input::default());
+        assert_eq!(w.tick(), 2);
+    }
+
+    #[test]
+    fn firing_spawns_a_bullet_and_an_event() {
+        let mut w = world(1);
+        let events = w.step(Input { fire: true, ..Input::default() });
+        assert!(events.contains(&GameEvent::PlayerFired));
+        assert_eq!(w.player_bullets.len(), 1);
+
+        // Classic fire allows one bullet on screen.
+        let events = w.step(Input { fire: true, ..Input::default() });
+        assert!(!events.contains(&GameEvent::PlayerFired));
+        assert_eq!(w.player_bullets.len(), 1);
+    }
+
+    #[test]
+    fn fleet_steps_on_its_own() {
+        let mut w = world(1);
+        let events = run(&mut w, 600);
+        assert!(events.contains(&GameEvent::FleetStepped));
+    }
+
+    #[test]
+    fn shooting_kills_aliens_and_scores() {
+        let mut w = world(1);
+        let events = run(&mut w, 1200);
+        let kills = events.iter().filter(|e| matches!(e, GameEvent::AlienKilled(..))).count();
+        assert!(kills > 0);
+        assert!(w.score > 0);
+    }
+
+    #[test]
+    fn shots_do_not_carry_into_the_next_wave() {
+        let mut w = world(1);
+        for a in w.aliens.iter_mut() {
+            a.alive = false;
+        }
+        w.enemy_bullets.push(Bullet::new(w.player.x, w.player.y - 100, 5));
+        w.player_bullets.push(Bullet::new(w.player.x, w.player.y - 100, -5));
+
+        let events = w.step(Input::default());
+        assert!(events.contains(&GameEvent::WaveCleared));
+        assert_eq!(w.wave_level, 2);
+        assert!(w.enemy_bullets.is_empty());
+        assert!(w.player_bullets.is_empty());
+    }
+
+    #[test]
+    fn fleet_reaching_the_player_row_ends_the_game() {
+        let mut w = world(1);
+        assert!(w.player.lives > 1);
+        let bottom = fleet_manager(&w.aliens).unwrap().2;
+        let dy = w.player.y - bottom;
+        for a in w.aliens.iter_mut() {
+            a.translate(0, dy);
+        }
+        w.step_interval = 0;
+
+        let events = w.step(Input::default());
+        assert!(events.contains(&GameEvent::FleetStepped));
+        assert!(events.contains(&GameEvent::GameOver(LossReason::Invaded)));
+        assert_eq!(w.player.lives, 0);
+    }
+
+    fn solid_shield() -> Shield {
+        Shield { sprite: vec![vec![1; 6]; 4], color: [0, 255, 0], x: 100, y: 200, alive: true }
+    }
+
+    /// Drops an enemy shot one cell wide onto column `col` of the first
+    /// bunker and lets it fall until it is gone or past the bunker.
+    fn drop_on_shield(w: &mut World, col: usize) -> Events {
+        let p = PIXEL as i32;
+        let s = &w.shields[0];
+        let (x, top, bottom) = (s.x + col as i32 * p, s.y, s.y + s.h());
+        w.enemy_bullets = vec![Bullet::new(x, top - p * 3, p)];
+        let mut events = Events::new();
+        while w.enemy_bullets.first().is_some_and(|b| b.y < bottom) {
+            w.update_enemy_bullets(&mut events);
+        }
+        events
+    }
+
+    #[test]
+    fn shots_bite_the_near_face_of_a_shield() {
+        let mut s = solid_shield();
+        let p = PIXEL as i32;
+        let shot = Rect::new(s.x + 2 * p, s.y, PIXEL, PIXEL * 2);
+        assert_eq!(s.hit_cell(shot, false), Some((0, 2)));
+        assert_eq!(s.hit_cell(shot, true), Some((1, 2)));
+
+        s.blast((1, 2), &mut Rng::new(1));
+        let cleared: Vec<(usize, usize)> = (0..4)
+            .flat_map(|r| (0..6).map(move |c| (r, c)))
+            .filter(|&(r, c)| s.sprite[r][c] == 0)
+            .collect();
+        assert!(cleared.contains(&(1, 2)));
+        assert!(cleared.iter().all(|&(r, c)| r.abs_diff(1) <= 1 && c.abs_diff(2) <= 1));
+        assert!(s.alive);
+    }
+
+    #[test]
+    fn aliens_erode_the_cells_they_cover() {
+        let mut s = solid_shield();
+        let p = PIXEL as i32;
+        s.erode(Rect::new(s.x - 10, s.y - 10, 10 + p as u32, 100));
+        assert!(s.sprite.iter().all(|row| row[0] == 0 && row[1..].iter().all(|&c| c == 1)));
+        assert!(s.alive);
+
+        s.erode(s.rect());
+        assert!(!s.alive);
+        assert_eq!(s.hit_cell(s.rect(), false), None);
+    }
+
+    #[test]
+    fn enemy_shots_fall_through_holes_in_a_shield() {
+        let mut w = world(1);
+        let events = drop_on_shield(&mut w, 2);
+        assert!(matches!(events[..], [GameEvent::ShieldHit(_)]));
+        assert!(w.enemy_bullets.is_empty());
+
+        for row in w.shields[0].sprite.iter_mut() {
+            row[2] = 0;
+        }
+        let events = drop_on_shield(&mut w, 2);
+        assert!(events.is_empty());
+        assert_eq!(w.enemy_bullets.len(), 1);
+    }
+
+    /// Drops a capsule of `kind` right onto the gun.
+    fn capsule_on_player(w: &mut World, kind: PowerUpKind) {
+        let capsule = w.capsule_sprites.spawn(kind, w.player.x + w.player.w() / 2, w.player.y - PIXEL as i32);
+        w.capsules.push(capsule);
+    }
+
+    #[test]
+    fn timed_power_ups_are_collected_and_run_out() {
+        let mut w = world(1);
+        capsule_on_player(&mut w, PowerUpKind::RapidFire);
+        let events = w.step(Input::default());
+        assert!(events.contains(&GameEvent::PowerUpCollected(PowerUpKind::RapidFire)));
+        assert!(w.capsules.is_empty());
+        assert!(w.player.effects.active(PowerUpKind::RapidFire));
+
+        let duration = PowerUpKind::RapidFire.duration().unwrap();
+        for _ in 1..duration {
+            w.step(Input::default());
+        }
+        assert!(w.player.effects.active(PowerUpKind::RapidFire));
+        w.step(Input::default());
+        assert!(!w.player.effects.active(PowerUpKind::RapidFire));
+    }
+
+    #[test]
+    fn extra_life_is_capped() {
+        let mut w = world(1);
+        let lives = w.player.lives;
+        capsule_on_player(&mut w, PowerUpKind::ExtraLife);
+        w.step(Input::default());
+        assert_eq!(w.player.lives, lives + 1);
+
+        w.player.lives = MAX_LIVES as i32;
+        capsule_on_player(&mut w, PowerUpKind::ExtraLife);
+        w.step(Input::default());
+        assert_eq!(w.player.lives, MAX_LIVES as i32);
+    }
+
+    /// Puts a still player shot in the middle of alien `i`.
+    fn shoot_alien(w: &mut World, i: usize) -> Events {
+        let a = &w.aliens[i];
+        w.player_bullets.push(Bullet::new(a.x + a.w() / 2, a.y + a.h() / 2, 0));
+        let mut events = Events::new();
+        w.update_player_bullets(&mut events);
+        events
+    }
+
+    #[test]
+    fn aliens_score_and_take_hits_by_kind() {
+        let mut w = world(1);
+        w.wave_level = 3;
+        w.start_wave();
+        // Wave 3 opens with a row of guards: 2 hit points, 40 points.
+        let guard = 0;
+        assert_eq!(w.aliens[guard].hit_points, 2);
+
+        let events = shoot_alien(&mut w, guard);
+        assert!(matches!(events[..], [GameEvent::AlienHit(_)]));
+        assert!(w.aliens[guard].alive);
+        assert_eq!(w.score, 0);
+
+        let events = shoot_alien(&mut w, guard);
+        assert!(matches!(events[..], [GameEvent::AlienKilled(_, 40)]));
+        assert!(!w.aliens[guard].alive);
+        assert_eq!(w.score, 40);
+
+        // The next row is squids, killed by one hit for 30.
+        let squid = w.aliens.iter().position(|a| a.y > w.aliens[guard].y).unwrap();
+        let events = shoot_alien(&mut w, squid);
+        assert!(matches!(events[..], [GameEvent::AlienKilled(_, 30)]));
+        assert_eq!(w.score, 70);
+    }
+
+    #[test]
+    fn rows_of_different_widths_share_a_centre() {
+        let w = world(1);
+        let def = WaveDef { rows: vec!["mothership".into(), "crab".into(), "heart".into()], columns: 5, ..w.waves[0].clone() };
+        let aliens = wave(&w.sprites, &w.kinds, &def);
+
+        let centres: Vec<i32> = aliens.chunks(5)
+            .map(|row| (row[0].x + row[4].x + row[4].w()) / 2)
+            .collect();
+        assert_eq!(centres.len(), 3);
+        assert!(centres.iter().all(|&c| (c - centres[0]).abs() <= PIXEL as i32), "{:?}", centres);
+        assert!(aliens[0].w() > aliens[5].w() && aliens[5].w() > aliens[10].w());
+    }
+
+    /// Collides a player shot with an enemy shot on top of it and reports
+    /// whether each survived, and whether they met at all.
+    fn meet(collision: ShotCollision, pierce: bool) -> (bool, bool, bool) {
+        let mut w = world(1);
+        let sprite = w.shot_sprites[ShotKind::Rolling as usize].clone();
+        w.enemy_bullets = vec![Bullet::shot(sprite, collision, 400, 300, 0)];
+        w.player_bullets = vec![Bullet { pierce, ..Bullet::new(400, 302, 0) }];
+        let mut events = Events::new();
+        w.collide_bullets(&mut events);
+        let met = matches!(events[..], [GameEvent::ShotsCollided(_)]);
+        (!w.player_bullets.is_empty(), !w.enemy_bullets.is_empty(), met)
+    }
+
+    #[test]
+    fn shots_cancel_or_pierce_by_type() {
+        assert_eq!(meet(ShotCollision::Cancel, false), (false, false, true));
+        assert_eq!(meet(ShotCollision::Pierce, false), (false, true, true));
+        assert_eq!(meet(ShotCollision::Cancel, true), (true, false, true));
+        // Two piercing shots pass through each other.
+        assert_eq!(meet(ShotCollision::Pierce, true), (true, true, false));
+    }
+
+    #[test]
+    fn plunger_comes_from_the_column_above_the_player() {
+        let mut w = world(1);
+        let shooters = bottom_shooters(&w.aliens);
+        let target = shooters[shooters.len() / 2];
+        w.player.x = w.aliens[target].x + w.aliens[target].w() / 2 - w.player.w() / 2;
+        assert_eq!(w.shooter_above_player(&shooters), Some(target));
+
+        let leftmost = *shooters.iter().min_by_key(|&&i| w.aliens[i].x).unwrap();
+        w.player.x = 0;
+        assert_eq!(w.shooter_above_player(&shooters), Some(leftmost));
+        assert_eq!(w.shooter_above_player(&[]), None);
+    }
+
+    #[test]
+    fn mothership_score_follows_the_shot_count() {
+        let first_lap: Vec<i32> = (1..=15).map(mothership_score).collect();
+        assert_eq!(first_lap, [50, 50, 100, 150, 100, 100, 50, 300, 100, 100, 100, 50, 150, 100, 100]);
+
+        let worth_300: Vec<u32> = (1..=60).filter(|&n| mothership_score(n) == 300).collect();
+        assert_eq!(worth_300, [8, 23, 38, 53]);
+    }
+
+    #[test]
+    fn mothership_hit_scores_by_shots_fired() {
+        let mut w = world(1);
+        // Just above the gun, so the shot cannot miss it.
+        w.mothership.alive = true;
+        w.mothership.x = w.player.x + w.player.w() / 2 - w.mothership.w() / 2;
+        w.mothership.y = w.player.y - w.mothership.h() - PIXEL as i32 * 3;
+        w.shots_fired = 22;
+
+        let mut events = w.step(Input { fire: true, ..Input::default() });
+        for _ in 0..5 {
+            if events.iter().any(|e| matches!(e, GameEvent::MothershipKilled(..))) {
+                break;
+            }
+            events = w.step(Input::default());
+        }
+        let value = events.iter().find_map(|e| match e {
+            GameEvent::MothershipKilled(_, value) => Some(*value),
+            _ => None,
+        });
+        assert_eq!(value, Some(300));
+    }
+
+    #[test]
+    fn plunger_never_comes_from_an_alien_that_does_not_fire() {
+        let mut w = world(1);
+        let shooters = bottom_shooters(&w.aliens);
+        let target = shooters[shooters.len() / 2];
+        w.player.x = w.aliens[target].x + w.aliens[target].w() / 2 - w.player.w() / 2;
+        let silent = Rc::new(AlienKind { fire_rate: 0.0, ..AlienKind::plain(w.aliens[target].kind.sprite.clone()) });
+        w.aliens[target].kind = silent.clone();
+
+        let picked = w.shooter_above_player(&shooters).unwrap();
+        assert_ne!(picked, target);
+        assert!(w.aliens[picked].kind.fire_rate > 0.0);
+
+        for &i in &shooters {
+            w.aliens[i].kind = silent.clone();
+        }
+        assert_eq!(w.shooter_above_player(&shooters), None);
+    }
+
+    #[test]
+    fn same_seed_and_inputs_play_the_same() {
+        let (mut a, mut b) = (world(42), world(42));
+        assert_eq!(run(&mut a, 3000), run(&mut b, 3000));
+        assert_eq!(a.score, b.score);
+        assert_eq!(a.player.lives, b.player.lives);
+        assert_eq!(a.aliens.iter().map(|a| (a.x, a.y, a.alive)).collect::<Vec<_>>(),
+                   b.aliens.iter().map(|a| (a.x, a.y, a.alive)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn reset_replays_the_same_run() {
+        let mut w = world(7);
+        let first = run(&mut w, 2000);
+        w.reset(7, Loadout::default());
+        assert_eq!(w.tick(), 0);
+        assert_eq!(run(&mut w, 2000), first);
+    }
+}